@@ -0,0 +1,40 @@
+//! Regenerates src/blueprotobuf_package.rs and src/blueprotobuf_package.serde.rs from
+//! src/BlueProtobuf.proto:
+//!
+//! ```sh
+//! cargo run --features codegen --bin regen-protobuf
+//! ```
+//!
+//! The schema isn't checked in, it has to be placed at src/BlueProtobuf.proto first. The
+//! generated files are checked in, so regular builds need neither the schema compiler nor
+//! this step. protox compiles the schema in-process, so no protoc install is needed.
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let src_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src");
+    let proto_file = src_dir.join("BlueProtobuf.proto");
+
+    let mut compiler = protox::Compiler::new([&src_dir])?;
+    compiler
+        .include_source_info(true)
+        .include_imports(true)
+        .open_files([&proto_file])?;
+    let file_descriptor_set = compiler.file_descriptor_set();
+    let file_descriptor_set_bytes = compiler.encode_file_descriptor_set();
+
+    prost_build::Config::new()
+        .out_dir(&src_dir)
+        // Override prost-types with pbjson-types
+        .compile_well_known_types()
+        .type_attribute(".blueprotobuf_package", "#[derive(specta::Type)]")
+        .extern_path(".google.protobuf", "::pbjson_types")
+        .compile_fds(file_descriptor_set)?;
+
+    pbjson_build::Builder::new()
+        .out_dir(&src_dir)
+        .register_descriptors(&file_descriptor_set_bytes)?
+        .build(&[".blueprotobuf_package"])?;
+
+    println!("Regenerated protobuf code in {}", src_dir.display());
+    Ok(())
+}
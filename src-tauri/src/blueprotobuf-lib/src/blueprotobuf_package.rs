@@ -6703,7 +6703,11 @@ pub enum SystemType {
     Windows = 5,
 }
 impl SystemType {
-   pub fn as_str_name(&self) -> &'static str {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
         match self {
             Self::Null => "SystemType_Null",
             Self::Android => "SystemType_Android",
@@ -6735,6 +6739,10 @@ pub enum BodyPartState {
     Dead = 2,
 }
 impl BodyPartState {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
     pub fn as_str_name(&self) -> &'static str {
         match self {
             Self::Default => "BodyPartStateDefault",
@@ -6764,6 +6772,10 @@ pub enum EDamageSource {
     Other = 100,
 }
 impl EDamageSource {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
     pub fn as_str_name(&self) -> &'static str {
         match self {
             Self::Skill => "EDamageSourceSkill",
@@ -6774,6 +6786,7 @@ impl EDamageSource {
             Self::Other => "EDamageSourceOther",
         }
     }
+    /// Creates an enum from field names used in the ProtoBuf definition.
     pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
         match value {
             "EDamageSourceSkill" => Some(Self::Skill),
@@ -11828,6 +11841,7 @@ impl EFarmlandState {
             Self::Over => "EFarmlandStateOver",
         }
     }
+    /// Creates an enum from field names used in the ProtoBuf definition.
     pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
         match value {
             "EFarmlandStateEmpty" => Some(Self::Empty),
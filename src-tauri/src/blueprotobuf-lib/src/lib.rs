@@ -1,16 +1,36 @@
 pub mod blueprotobuf {
     include!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/blueprotobuf_package.rs"));
+    // pbjson serde impls, only needed by tools that print messages as JSON
+    #[cfg(feature = "serde")]
     include!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/blueprotobuf_package.serde.rs"));
 }
 
+pub mod wire;
+
 use crate::blueprotobuf::EEntityType;
 
+/// The entity type is stored in the low 16 bits of an entity uuid, shifted left by 6.
 impl From<i64> for EEntityType {
-    fn from(entity_type: i64) -> Self {
-        match entity_type & 0xffff {
-            64 => EEntityType::EntMonster,
-            640 => EEntityType::EntChar,
-            _ => EEntityType::EntErrType,
+    fn from(entity_uuid: i64) -> Self {
+        let entity_type = ((entity_uuid & 0xffff) >> 6) as i32;
+        match EEntityType::try_from(entity_type) {
+            Ok(EEntityType::EntCount) | Err(_) => EEntityType::EntErrType,
+            Ok(entity_type) => entity_type,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::blueprotobuf::EEntityType;
+
+    #[test]
+    fn test_entity_type_from_uuid() {
+        assert_eq!(EEntityType::from((1234 << 16) | 64), EEntityType::EntMonster);
+        assert_eq!(EEntityType::from((1234 << 16) | 640), EEntityType::EntChar);
+        assert_eq!(EEntityType::from(2 << 6), EEntityType::EntNpc);
+        assert_eq!(EEntityType::from(19 << 6), EEntityType::EntVehicle);
+        assert_eq!(EEntityType::from(4 << 6), EEntityType::EntErrType);
+        assert_eq!(EEntityType::from(23 << 6), EEntityType::EntErrType);
+    }
+}
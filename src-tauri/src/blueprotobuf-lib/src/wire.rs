@@ -0,0 +1,255 @@
+//! Schemaless protobuf wire format decoding.
+//!
+//! Walks raw protobuf bytes into a tree of fields without knowing the message type, guessing
+//! how each value was meant to be read. Used to inspect payloads that fail to decode after the
+//! game's schema changed.
+
+use std::fmt::{self, Write};
+
+const MAX_DEPTH: usize = 16;
+const MAX_BYTES_SHOWN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireType {
+    Varint,
+    Fixed64,
+    LengthDelimited,
+    Fixed32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireValue {
+    /// A varint, along with its zigzag (`sint`) reading.
+    Varint { value: u64, zigzag: i64 },
+    Fixed64 { value: u64, double: f64 },
+    Fixed32 { value: u32, float: f32 },
+    /// A length-delimited value that parses as a nested message.
+    Message(Vec<WireField>),
+    /// A length-delimited value that is printable UTF-8.
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WireField {
+    pub number: u32,
+    pub wire_type: WireType,
+    pub value: WireValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireError {
+    Truncated,
+    VarintOverflow,
+    /// Groups (wire types 3 and 4) are deprecated and not supported, 6 and 7 don't exist.
+    UnsupportedWireType(u8),
+    InvalidFieldNumber(u64),
+    TooDeep,
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::Truncated => write!(f, "truncated field"),
+            WireError::VarintOverflow => write!(f, "varint longer than 10 bytes"),
+            WireError::UnsupportedWireType(wire_type) => {
+                write!(f, "unsupported wire type {wire_type}")
+            }
+            WireError::InvalidFieldNumber(number) => write!(f, "invalid field number {number}"),
+            WireError::TooDeep => write!(f, "messages nested deeper than {MAX_DEPTH}"),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+/// Decodes `data` as a protobuf message. Fails unless every byte belongs to a field.
+pub fn decode(data: &[u8]) -> Result<Vec<WireField>, WireError> {
+    decode_fields(data, 0)
+}
+
+/// Formats the fields of `data` as an indented tree, one field per line.
+/// Decoding is best effort, bytes after the first malformed field are reported but not shown.
+pub fn dump(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut remaining = data;
+    while !remaining.is_empty() {
+        match decode_field(&mut remaining, 0) {
+            Ok(field) => write_field(&mut out, &field, 0),
+            Err(e) => {
+                let _ = writeln!(out, "<{e}, {} bytes not decoded>", remaining.len());
+                break;
+            }
+        }
+    }
+    out
+}
+
+fn decode_fields(data: &[u8], depth: usize) -> Result<Vec<WireField>, WireError> {
+    if depth > MAX_DEPTH {
+        return Err(WireError::TooDeep);
+    }
+    let mut remaining = data;
+    let mut fields = Vec::new();
+    while !remaining.is_empty() {
+        fields.push(decode_field(&mut remaining, depth)?);
+    }
+    Ok(fields)
+}
+
+/// Decodes one field, advancing `data` past it only on success.
+fn decode_field(data: &mut &[u8], depth: usize) -> Result<WireField, WireError> {
+    let mut cursor = *data;
+    let key = read_varint(&mut cursor)?;
+    let number = key >> 3;
+    if number == 0 || number > u64::from(u32::MAX >> 3) {
+        return Err(WireError::InvalidFieldNumber(number));
+    }
+    let (wire_type, value) = match key & 0x7 {
+        0 => {
+            let value = read_varint(&mut cursor)?;
+            let zigzag = ((value >> 1) as i64) ^ -((value & 1) as i64);
+            (WireType::Varint, WireValue::Varint { value, zigzag })
+        }
+        1 => {
+            let bytes = take(&mut cursor, 8)?;
+            let value = u64::from_le_bytes(bytes.try_into().map_err(|_| WireError::Truncated)?);
+            let double = f64::from_bits(value);
+            (WireType::Fixed64, WireValue::Fixed64 { value, double })
+        }
+        2 => {
+            let len = read_varint(&mut cursor)?;
+            let len = usize::try_from(len).map_err(|_| WireError::Truncated)?;
+            let bytes = take(&mut cursor, len)?;
+            (WireType::LengthDelimited, guess_length_delimited(bytes, depth))
+        }
+        5 => {
+            let bytes = take(&mut cursor, 4)?;
+            let value = u32::from_le_bytes(bytes.try_into().map_err(|_| WireError::Truncated)?);
+            let float = f32::from_bits(value);
+            (WireType::Fixed32, WireValue::Fixed32 { value, float })
+        }
+        wire_type => return Err(WireError::UnsupportedWireType(wire_type as u8)),
+    };
+    *data = cursor;
+    Ok(WireField {
+        number: number as u32,
+        wire_type,
+        value,
+    })
+}
+
+/// Printable text is taken as a string before trying a nested message, since short strings
+/// often happen to be valid messages too.
+fn guess_length_delimited(bytes: &[u8], depth: usize) -> WireValue {
+    if let Ok(text) = std::str::from_utf8(bytes)
+        && !text.is_empty()
+        && text.chars().all(|c| !c.is_control() || c.is_whitespace())
+    {
+        return WireValue::String(text.to_string());
+    }
+    match decode_fields(bytes, depth + 1) {
+        Ok(fields) if !fields.is_empty() => WireValue::Message(fields),
+        _ => WireValue::Bytes(bytes.to_vec()),
+    }
+}
+
+fn read_varint(data: &mut &[u8]) -> Result<u64, WireError> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().enumerate() {
+        if i == 10 {
+            return Err(WireError::VarintOverflow);
+        }
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            *data = &data[i + 1..];
+            return Ok(value);
+        }
+    }
+    Err(WireError::Truncated)
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], WireError> {
+    if data.len() < len {
+        return Err(WireError::Truncated);
+    }
+    let (value, rest) = data.split_at(len);
+    *data = rest;
+    Ok(value)
+}
+
+fn write_field(out: &mut String, field: &WireField, indent: usize) {
+    let pad = "  ".repeat(indent);
+    let number = field.number;
+    let _ = match &field.value {
+        WireValue::Varint { value, zigzag } => {
+            writeln!(out, "{pad}{number}: varint {value} (zigzag {zigzag})")
+        }
+        WireValue::Fixed64 { value, double } => {
+            writeln!(out, "{pad}{number}: fixed64 {value} (double {double})")
+        }
+        WireValue::Fixed32 { value, float } => {
+            writeln!(out, "{pad}{number}: fixed32 {value} (float {float})")
+        }
+        WireValue::String(text) => writeln!(out, "{pad}{number}: string {text:?}"),
+        WireValue::Bytes(bytes) => {
+            let hex: String = bytes
+                .iter()
+                .take(MAX_BYTES_SHOWN)
+                .map(|b| format!("{b:02x}"))
+                .collect();
+            let ellipsis = if bytes.len() > MAX_BYTES_SHOWN { ".." } else { "" };
+            writeln!(out, "{pad}{number}: bytes[{}] {hex}{ellipsis}", bytes.len())
+        }
+        WireValue::Message(fields) => {
+            let _ = writeln!(out, "{pad}{number}: message {{");
+            for nested in fields {
+                write_field(out, nested, indent + 1);
+            }
+            writeln!(out, "{pad}}}")
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blueprotobuf::{SyncNearEntities, SyncServerTime};
+    use crate::wire::{decode, dump, WireError, WireValue};
+    use prost::Message;
+
+    #[test]
+    fn test_decode_guesses_values() {
+        let message = SyncServerTime {
+            client_milliseconds: Some(-3),
+            server_milliseconds: Some(1_700_000_000_000),
+        };
+        let fields = decode(&message.encode_to_vec()).unwrap();
+        assert_eq!(fields.len(), 2);
+        // int64 -3 is a ten byte varint, its zigzag reading is meaningless
+        assert!(matches!(fields[0].value, WireValue::Varint { value, .. } if value as i64 == -3));
+        assert!(matches!(fields[1].value, WireValue::Varint { value: 1_700_000_000_000, .. }));
+    }
+
+    #[test]
+    fn test_decode_nested_messages() {
+        let message = SyncNearEntities {
+            appear: vec![Default::default()],
+            disappear: vec![crate::blueprotobuf::DisappearEntity {
+                uuid: Some(42),
+                ..Default::default()
+            }],
+        };
+        let fields = decode(&message.encode_to_vec()).unwrap();
+        assert_eq!(fields[1].number, 2);
+        let WireValue::Message(disappear) = &fields[1].value else {
+            panic!("expected a nested message, got {:?}", fields[1].value);
+        };
+        assert!(matches!(disappear[0].value, WireValue::Varint { value: 42, .. }));
+    }
+
+    #[test]
+    fn test_dump_reports_undecoded_bytes() {
+        assert_eq!(decode(&[0x08, 0x96]), Err(WireError::Truncated));
+        assert_eq!(dump(&[0x08, 0x96, 0x01, 0x10]), "1: varint 150 (zigzag 75)\n<truncated field, 1 bytes not decoded>\n");
+    }
+}
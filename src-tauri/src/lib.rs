@@ -5,10 +5,13 @@ mod packets;
 use crate::build_app::build;
 use crate::live::opcodes_models::{Encounter, EncounterMutex};
 use crate::live::crowdsource_persistence::{apply_snapshot_to_encounter, load_snapshot};
+use crate::live::message_journal::MESSAGE_JOURNAL;
+use crate::packets::opcode_discovery::OPCODE_DISCOVERY;
+use crate::packets::session_recorder::SESSION_RECORDER;
 use log::{info, warn};
 use std::process::Command;
 
-use tauri::menu::MenuBuilder;
+use tauri::menu::{CheckMenuItem, CheckMenuItemBuilder, MenuBuilder};
 use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
 use tauri::window::Color;
 use tauri::{LogicalPosition, LogicalSize, Manager, Position, Size, Window, WindowEvent};
@@ -19,6 +22,9 @@ use tauri_specta::{collect_commands, Builder};
 pub const WINDOW_LIVE_LABEL: &str = "live";
 pub const WINDOW_MAIN_LABEL: &str = "main";
 
+/// Tray item showing whether session recording is on.
+struct RecordingMenuItem(CheckMenuItem<tauri::Wry>);
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     std::panic::set_hook(Box::new(|info| {
@@ -31,10 +37,20 @@ pub fn run() {
     let builder = Builder::<tauri::Wry>::new()
         .commands(collect_commands![
             live::commands::get_crowdsourced_monster,
+            live::commands::get_tracked_bosses,
             live::commands::get_crowdsourced_monster_options,
             live::commands::set_crowdsourced_monster_remote,
             live::commands::get_local_player_line,
             live::commands::mark_current_crowdsourced_line_dead,
+            live::commands::get_session_recording,
+            live::commands::set_session_recording,
+            live::commands::get_message_journal_settings,
+            live::commands::set_message_journal_settings,
+            live::commands::get_capture_status,
+            live::commands::restart_capture,
+            live::commands::get_opcode_discovery,
+            live::commands::set_opcode_discovery,
+            live::commands::export_opcode_discovery,
         ]);
 
     #[cfg(debug_assertions)]
@@ -67,6 +83,7 @@ pub fn run() {
             // Setup stuff
             setup_logs(&app_handle).expect("failed to setup logs");
             setup_tray(&app_handle).expect("failed to setup tray");
+            setup_debug_outputs(&app_handle);
 
             if let Some(live_window) = app_handle.get_webview_window(WINDOW_LIVE_LABEL) {
                 if let Err(e) = live_window.set_background_color(Some(Color(0, 0, 0, 0))) {
@@ -201,6 +218,22 @@ fn setup_logs(app: &tauri::AppHandle) -> tauri::Result<()> {
     Ok(())
 }
 
+fn setup_debug_outputs(app: &tauri::AppHandle) {
+    let app_data_dir = match app.path().app_data_dir() {
+        Ok(app_data_dir) => app_data_dir,
+        Err(e) => {
+            warn!("failed to resolve app data dir for session recordings and journals: {e}");
+            return;
+        }
+    };
+    SESSION_RECORDER.lock().unwrap().configure(
+        app_data_dir.join("recordings"),
+        app.package_info().version.to_string(),
+    );
+    MESSAGE_JOURNAL.lock().unwrap().configure(app_data_dir.join("journal"));
+    OPCODE_DISCOVERY.lock().unwrap().configure(app_data_dir.join("discovery"));
+}
+
 fn setup_tray(app: &tauri::AppHandle) -> tauri::Result<()> {
     fn show_window(window: &tauri::WebviewWindow) -> tauri::Result<()> {
         window.show()?;
@@ -212,6 +245,11 @@ fn setup_tray(app: &tauri::AppHandle) -> tauri::Result<()> {
         Ok(())
     }
 
+    let recording_item = CheckMenuItemBuilder::with_id("toggle-recording", "Session Recording")
+        .checked(SESSION_RECORDER.lock().unwrap().is_enabled())
+        .build(app)?;
+    app.manage(RecordingMenuItem(recording_item.clone()));
+
     let menu = MenuBuilder::new(app)
         .text("show-settings", "Show Settings")
         .separator()
@@ -219,6 +257,9 @@ fn setup_tray(app: &tauri::AppHandle) -> tauri::Result<()> {
         .text("reset", "Reset Window")
         .text("disable-clickthrough", "Disable Clickthrough")
         .separator()
+        .item(&recording_item)
+        .text("restart-capture", "Restart Capture")
+        .separator()
         .text("quit", "Quit")
         .build()?;
 
@@ -272,6 +313,21 @@ fn setup_tray(app: &tauri::AppHandle) -> tauri::Result<()> {
                     warn!("failed to disable clickthrough: {e}");
                 }
             }
+            "toggle-recording" => {
+                {
+                    let mut recorder = SESSION_RECORDER.lock().unwrap();
+                    let enabled = !recorder.is_enabled();
+                    if let Err(e) = recorder.set_enabled(enabled) {
+                        warn!("failed to toggle session recording: {e}");
+                    }
+                }
+                sync_recording_menu_item(tray_app);
+            }
+            "restart-capture" => {
+                if let Err(e) = packets::packet_capture::restart_capture() {
+                    warn!("failed to restart capture: {e}");
+                }
+            }
             "quit" => {
                 stop_windivert();
                 tray_app.exit(0);
@@ -299,6 +355,17 @@ fn setup_tray(app: &tauri::AppHandle) -> tauri::Result<()> {
     Ok(())
 }
 
+/// Checks the tray item when session recording is on, the click itself toggles the
+/// check mark even when enabling recording failed.
+pub fn sync_recording_menu_item(app: &tauri::AppHandle) {
+    let enabled = SESSION_RECORDER.lock().unwrap().is_enabled();
+    if let Some(item) = app.try_state::<RecordingMenuItem>() {
+        if let Err(e) = item.0.set_checked(enabled) {
+            warn!("failed to update the session recording tray item: {e}");
+        }
+    }
+}
+
 fn on_window_event_fn(window: &Window, event: &WindowEvent) {
     match event {
         // when you click the X button to close a window, don't close it - hide it!
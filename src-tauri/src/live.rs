@@ -1,7 +1,10 @@
 // https://doc.rust-lang.org/reference/items/modules.html#module-source-filenames
 // Preferred way is to name modules with their subfolder name now (no longer mod.rs)
+pub mod attr_codec;
 pub mod commands;
+mod dirty_data;
 pub mod live_main;
+pub mod message_journal;
 pub mod opcodes_models;
 mod opcodes_process;
 pub mod bptimer_stream;
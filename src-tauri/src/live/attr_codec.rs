@@ -0,0 +1,208 @@
+use crate::live::opcodes_models::attr_type;
+use blueprotobuf_lib::blueprotobuf;
+use std::fmt;
+
+/// How the raw bytes of an attribute are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttrKind {
+    Varint,
+    /// A varint holding an `int64`, HP of world bosses doesn't fit in an `i32`.
+    Hp,
+    /// A varint length followed by UTF-8 bytes.
+    String,
+    // no attribute id is registered with the kinds below until one is confirmed from a capture
+    /// A little-endian `f32`.
+    #[allow(dead_code)]
+    Float,
+    /// A `blueprotobuf::Position` message.
+    #[allow(dead_code)]
+    Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrValue {
+    Varint(u64),
+    Hp(i64),
+    String(String),
+    Float(f32),
+    Position { x: f32, y: f32, z: f32 },
+}
+
+impl AttrValue {
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            AttrValue::Varint(value) => i32::try_from(*value).ok(),
+            AttrValue::Hp(value) => i32::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttrError {
+    /// The id isn't in the attribute table, its value is left undecoded.
+    Unknown(i32),
+    Truncated { attr_id: i32 },
+    InvalidVarint { attr_id: i32 },
+    InvalidUtf8 { attr_id: i32 },
+    InvalidPosition { attr_id: i32, reason: String },
+    /// Bytes left over after the value.
+    TrailingBytes { attr_id: i32, len: usize },
+}
+
+impl fmt::Display for AttrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttrError::Unknown(attr_id) => write!(f, "unknown attribute {attr_id:#x}"),
+            AttrError::Truncated { attr_id } => write!(f, "attribute {attr_id:#x} is truncated"),
+            AttrError::InvalidVarint { attr_id } => {
+                write!(f, "attribute {attr_id:#x} has an invalid varint")
+            }
+            AttrError::InvalidUtf8 { attr_id } => {
+                write!(f, "attribute {attr_id:#x} is not valid UTF-8")
+            }
+            AttrError::InvalidPosition { attr_id, reason } => {
+                write!(f, "attribute {attr_id:#x} is not a position: {reason}")
+            }
+            AttrError::TrailingBytes { attr_id, len } => {
+                write!(f, "attribute {attr_id:#x} has {len} trailing bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AttrError {}
+
+pub fn attr_kind(attr_id: i32) -> Option<AttrKind> {
+    match attr_id {
+        attr_type::ATTR_NAME => Some(AttrKind::String),
+        attr_type::ATTR_ID => Some(AttrKind::Varint),
+        attr_type::ATTR_HP | attr_type::ATTR_MAX_HP => Some(AttrKind::Hp),
+        _ => None,
+    }
+}
+
+/// Decodes the raw bytes of an attribute according to the kind registered for its id.
+pub fn decode_attr(attr_id: i32, raw: &[u8]) -> Result<AttrValue, AttrError> {
+    let kind = attr_kind(attr_id).ok_or(AttrError::Unknown(attr_id))?;
+    decode_attr_as(kind, attr_id, raw)
+}
+
+/// Decodes the raw bytes of an attribute as `kind`, whatever its id is registered as.
+pub fn decode_attr_as(kind: AttrKind, attr_id: i32, raw: &[u8]) -> Result<AttrValue, AttrError> {
+    let mut data = raw;
+    let value = match kind {
+        AttrKind::Varint => AttrValue::Varint(read_varint(attr_id, &mut data)?),
+        AttrKind::Hp => AttrValue::Hp(read_varint(attr_id, &mut data)? as i64),
+        AttrKind::String => {
+            let len = read_varint(attr_id, &mut data)?;
+            let len = usize::try_from(len).map_err(|_| AttrError::Truncated { attr_id })?;
+            if data.len() < len {
+                return Err(AttrError::Truncated { attr_id });
+            }
+            let (bytes, rest) = data.split_at(len);
+            data = rest;
+            let text = std::str::from_utf8(bytes).map_err(|_| AttrError::InvalidUtf8 { attr_id })?;
+            AttrValue::String(text.to_string())
+        }
+        AttrKind::Float => {
+            let (bytes, rest) = data.split_first_chunk::<4>().ok_or(AttrError::Truncated { attr_id })?;
+            data = rest;
+            AttrValue::Float(f32::from_le_bytes(*bytes))
+        }
+        AttrKind::Position => {
+            let position = <blueprotobuf::Position as prost::Message>::decode(data).map_err(|e| {
+                AttrError::InvalidPosition {
+                    attr_id,
+                    reason: e.to_string(),
+                }
+            })?;
+            data = &[];
+            AttrValue::Position {
+                x: position.x.unwrap_or_default(),
+                y: position.y.unwrap_or_default(),
+                z: position.z.unwrap_or_default(),
+            }
+        }
+    };
+    if !data.is_empty() {
+        return Err(AttrError::TrailingBytes {
+            attr_id,
+            len: data.len(),
+        });
+    }
+    Ok(value)
+}
+
+fn read_varint(attr_id: i32, data: &mut &[u8]) -> Result<u64, AttrError> {
+    if data.is_empty() {
+        return Err(AttrError::Truncated { attr_id });
+    }
+    prost::encoding::decode_varint(data).map_err(|_| AttrError::InvalidVarint { attr_id })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::attr_codec::{decode_attr, decode_attr_as, AttrError, AttrKind, AttrValue};
+    use crate::live::opcodes_models::attr_type;
+    use blueprotobuf_lib::blueprotobuf;
+    use prost::Message;
+
+    #[test]
+    fn test_decode_known_attrs() {
+        let mut name = vec![6];
+        name.extend_from_slice("Player".as_bytes());
+        assert_eq!(decode_attr(attr_type::ATTR_NAME, &name), Ok(AttrValue::String("Player".to_string())));
+        // 3_000_000_000 doesn't fit in an i32
+        assert_eq!(decode_attr(attr_type::ATTR_MAX_HP, &[0x80, 0xbc, 0xc1, 0x96, 0x0b]), Ok(AttrValue::Hp(3_000_000_000)));
+    }
+
+    #[test]
+    fn test_malformed_attrs_are_errors() {
+        let attr_id = attr_type::ATTR_HP;
+        assert_eq!(decode_attr(attr_id, &[]), Err(AttrError::Truncated { attr_id }));
+        assert_eq!(decode_attr(attr_id, &[0x80]), Err(AttrError::InvalidVarint { attr_id }));
+        assert_eq!(decode_attr(attr_id, &[0x01, 0x02]), Err(AttrError::TrailingBytes { attr_id, len: 1 }));
+        let attr_id = attr_type::ATTR_NAME;
+        assert_eq!(decode_attr(attr_id, &[200, b'a']), Err(AttrError::Truncated { attr_id }));
+        assert_eq!(decode_attr(attr_id, &[1, 0xff]), Err(AttrError::InvalidUtf8 { attr_id }));
+        assert_eq!(decode_attr(0x7fff_ffff, &[0]), Err(AttrError::Unknown(0x7fff_ffff)));
+    }
+
+    #[test]
+    fn test_decode_float_and_position() {
+        // neither kind has a registered id
+        let attr_id = 0x7fff_ffff;
+        assert_eq!(decode_attr_as(AttrKind::Float, attr_id, &1.5f32.to_le_bytes()), Ok(AttrValue::Float(1.5)));
+        assert_eq!(decode_attr_as(AttrKind::Float, attr_id, &[0, 0]), Err(AttrError::Truncated { attr_id }));
+        assert_eq!(
+            decode_attr_as(AttrKind::Float, attr_id, &[0, 0, 0, 0, 1]),
+            Err(AttrError::TrailingBytes { attr_id, len: 1 })
+        );
+
+        let position = blueprotobuf::Position {
+            x: Some(1.5),
+            y: Some(-2.0),
+            z: Some(3.0),
+            dir: None,
+        };
+        assert_eq!(
+            decode_attr_as(AttrKind::Position, attr_id, &position.encode_to_vec()),
+            Ok(AttrValue::Position { x: 1.5, y: -2.0, z: 3.0 })
+        );
+        // missing coordinates are 0
+        let position = blueprotobuf::Position {
+            x: Some(4.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            decode_attr_as(AttrKind::Position, attr_id, &position.encode_to_vec()),
+            Ok(AttrValue::Position { x: 4.0, y: 0.0, z: 0.0 })
+        );
+        // a field 1 with the wrong wire type
+        assert!(matches!(
+            decode_attr_as(AttrKind::Position, attr_id, &[0x08, 0x01]),
+            Err(AttrError::InvalidPosition { .. })
+        ));
+    }
+}
@@ -1,10 +1,15 @@
 use crate::live::crowdsource_persistence::{save_snapshot, CrowdsourceMonsterSnapshot};
+use crate::live::message_journal::{MessageJournalSettings, MESSAGE_JOURNAL};
 use crate::live::opcodes_models::{
-    get_crowdsource_monster_choices, resolve_crowdsource_remote, EncounterMutex,
+    get_crowdsource_monster_choices, monster_name, resolve_crowdsource_remote, EncounterMutex,
+    HpReport, HpSample, LeftViewReason, MONSTER_UID_CROWDSOURCE_MAP,
 };
-use crate::live::bptimer_stream::{BPTIMER_BASE_URL, CREATE_HP_REPORT_ENDPOINT, CROWD_SOURCE_API_KEY};
+use crate::live::opcodes_process::{on_hp_report_sent, report_position, send_hp_report};
+use crate::packets::capture_stats::{CaptureStatus, CAPTURE_STATS};
+use crate::packets::opcode_discovery::{OpcodeDiscoveryReport, OPCODE_DISCOVERY};
+use crate::packets::packet_capture;
+use crate::packets::session_recorder::SESSION_RECORDER;
 use log::{info, warn};
-use reqwest::Client;
 use serde::Serialize;
 use specta::Type;
 
@@ -13,6 +18,28 @@ pub struct CrowdsourcedMonster {
     pub name: String,
     pub id: i32,
     pub remote_id: Option<String>,
+    /// Whether a monster with this id is currently in view of the local player.
+    pub in_view: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct TrackedBossStatus {
+    pub uid: i64,
+    pub monster_id: i32,
+    pub name: String,
+    pub remote_id: Option<String>,
+    pub line_id: Option<u32>,
+    pub curr_hp: Option<i64>,
+    pub max_hp: Option<i64>,
+    pub hp_pct: Option<i64>,
+    pub last_reported_hp_pct: Option<i64>,
+    pub in_view: bool,
+    /// Why the boss left view, `None` while it is in view.
+    pub left_view: Option<LeftViewReason>,
+    /// Whether its 0% report was sent.
+    pub death_reported: bool,
+    /// Most recent HP updates, oldest first.
+    pub hp_history: Vec<HpSample>,
 }
 
 #[derive(Debug, Clone, Serialize, Type)]
@@ -26,18 +53,44 @@ pub struct CrowdsourcedMonsterOption {
 #[specta::specta]
 pub fn get_crowdsourced_monster(state: tauri::State<'_, EncounterMutex>) -> Option<CrowdsourcedMonster> {
     let encounter = state.lock().unwrap();
-    match (
-        &encounter.crowdsource_monster_name,
-        encounter.crowdsource_monster_id,
-        encounter.crowdsource_monster_remote_id.as_ref(),
-    ) {
-        (Some(name), Some(id), remote_id) => Some(CrowdsourcedMonster {
-            name: name.clone(),
-            id,
-             remote_id: remote_id.cloned(),
-        }),
-        _ => None,
-    }
+    let id = encounter.current_monster_id()?;
+    Some(CrowdsourcedMonster {
+        name: monster_name(id),
+        id,
+        remote_id: MONSTER_UID_CROWDSOURCE_MAP.get(&id).cloned(),
+        in_view: encounter
+            .tracked_bosses
+            .values()
+            .any(|tracked_boss| tracked_boss.monster_id == id && tracked_boss.in_view()),
+    })
+}
+
+/// Crowdsourced monsters seen on this server, the ones in view first.
+#[tauri::command]
+#[specta::specta]
+pub fn get_tracked_bosses(state: tauri::State<'_, EncounterMutex>) -> Vec<TrackedBossStatus> {
+    let encounter = state.lock().unwrap();
+    let mut bosses: Vec<TrackedBossStatus> = encounter
+        .tracked_bosses
+        .iter()
+        .map(|(uid, tracked_boss)| TrackedBossStatus {
+            uid: *uid,
+            monster_id: tracked_boss.monster_id,
+            name: tracked_boss.name.clone(),
+            remote_id: tracked_boss.remote_id.clone(),
+            line_id: tracked_boss.line_id,
+            curr_hp: tracked_boss.curr_hp(),
+            max_hp: tracked_boss.max_hp,
+            hp_pct: tracked_boss.hp_pct(),
+            last_reported_hp_pct: tracked_boss.last_reported_hp_pct,
+            in_view: tracked_boss.in_view(),
+            left_view: tracked_boss.left_view,
+            death_reported: tracked_boss.death_reported,
+            hp_history: tracked_boss.hp_history.iter().copied().collect(),
+        })
+        .collect();
+    bosses.sort_by(|a, b| b.in_view.cmp(&a.in_view).then_with(|| a.name.cmp(&b.name)));
+    bosses
 }
 
 #[tauri::command]
@@ -67,9 +120,7 @@ pub fn set_crowdsourced_monster_remote(
         let mut encounter = encounter_state
             .lock()
             .map_err(|_| "Failed to lock encounter".to_string())?;
-        encounter.crowdsource_monster_id = Some(monster_id);
-        encounter.crowdsource_monster_name = Some(monster_name.clone());
-        encounter.crowdsource_monster_remote_id = Some(remote_id.clone());
+        encounter.pinned_monster_id = Some(monster_id);
     }
 
     let snapshot = CrowdsourceMonsterSnapshot {
@@ -94,14 +145,7 @@ pub fn get_local_player_line(state: tauri::State<'_, EncounterMutex>) -> Result<
         .lock()
         .map_err(|_| "Failed to lock encounter".to_string())?;
 
-    let line = encounter
-        .local_player
-        .as_ref()
-        .and_then(|player| player.v_data.as_ref())
-        .and_then(|v| v.scene_data.as_ref())
-        .and_then(|scene| scene.line_id);
-
-    Ok(line.map(|line| line as i32))
+    Ok(encounter.local_player.line_id.map(|line| line as i32))
 }
 
 #[tauri::command]
@@ -109,75 +153,119 @@ pub fn get_local_player_line(state: tauri::State<'_, EncounterMutex>) -> Result<
 pub async fn mark_current_crowdsourced_line_dead(
     encounter_state: tauri::State<'_, EncounterMutex>,
 ) -> Result<(), String> {
-    let (monster_id, monster_name, line, pos_x, pos_y) = {
+    let (report, monster_name) = {
         let encounter = encounter_state
             .lock()
             .map_err(|_| "Failed to lock encounter".to_string())?;
 
         let monster_id = encounter
-            .crowdsource_monster_id
+            .current_monster_id()
             .ok_or_else(|| "No crowdsourced monster ID available".to_string())?;
 
-        let monster_name = encounter
-            .crowdsource_monster_name
-            .clone()
-            .unwrap_or_else(|| "Unknown Monster".to_string());
+        let monster_name = monster_name(monster_id);
 
-        let scene_data = encounter
+        let line = encounter
             .local_player
-            .as_ref()
-            .and_then(|player| player.v_data.as_ref())
-            .and_then(|v| v.scene_data.as_ref())
-            .ok_or_else(|| "No local player scene data available".to_string())?;
-
-        let line = scene_data
             .line_id
             .ok_or_else(|| "No line id available for local player".to_string())?;
 
-        let pos = scene_data
-            .pos
-            .as_ref()
-            .ok_or_else(|| "No position data available for local player".to_string())?;
+        let uid = encounter.tracked_boss_uid(monster_id, line);
+        let boss_pos = uid
+            .and_then(|uid| encounter.tracked_bosses.get(&uid))
+            .and_then(|tracked_boss| tracked_boss.pos);
+        let (pos_x, pos_y) = report_position(boss_pos, &encounter.local_player)
+            .ok_or_else(|| "No position available for the boss or the local player".to_string())?;
 
-        let pos_x = pos
-            .x
-            .ok_or_else(|| "No pos_x available for local player".to_string())? as f64;
-
-        let pos_y = pos
-            .y
-            .ok_or_else(|| "No pos_y available for local player".to_string())? as f64;
-
-        (monster_id, monster_name, line, pos_x, pos_y)
+        let report = HpReport {
+            uid,
+            monster_id,
+            hp_pct: 0,
+            line,
+            pos_x,
+            pos_y,
+        };
+        (report, monster_name)
     };
 
     info!(
         "mark_current_crowdsourced_line_dead - reporting monster '{}' ({}) as dead on line {}",
-        monster_name, monster_id, line
+        monster_name, report.monster_id, report.line
     );
 
-    let body = serde_json::json!({
-        "monster_id": monster_id,
-        "hp_pct": 0,
-        "line": line,
-        "pos_x": pos_x,
-        "pos_y": pos_y,
-    });
-
-    let client = Client::new();
-    let response = client
-        .post(format!("{BPTIMER_BASE_URL}{CREATE_HP_REPORT_ENDPOINT}"))
-        .header("X-API-Key", CROWD_SOURCE_API_KEY)
-        .json(&body)
-        .send()
-        .await
-        .map_err(|err| format!("Failed to send HP report: {err}"))?;
-
-    if !response.status().is_success() {
-        return Err(format!(
-            "HP report failed with status {}",
-            response.status()
-        ));
-    }
+    send_hp_report(&report).await?;
+
+    // the automatic death report would only repeat this one
+    let mut encounter = encounter_state
+        .lock()
+        .map_err(|_| "Failed to lock encounter".to_string())?;
+    on_hp_report_sent(&mut encounter, &report, true);
 
     Ok(())
 }
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_session_recording() -> bool {
+    SESSION_RECORDER.lock().unwrap().is_enabled()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_session_recording(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    let result = SESSION_RECORDER
+        .lock()
+        .map_err(|_| "Failed to lock session recorder".to_string())?
+        .set_enabled(enabled);
+    crate::sync_recording_menu_item(&app);
+    result
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_message_journal_settings() -> MessageJournalSettings {
+    MESSAGE_JOURNAL.lock().unwrap().settings().clone()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_message_journal_settings(settings: MessageJournalSettings) -> Result<(), String> {
+    MESSAGE_JOURNAL
+        .lock()
+        .map_err(|_| "Failed to lock message journal".to_string())?
+        .apply_settings(settings)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_capture_status() -> CaptureStatus {
+    CAPTURE_STATS.lock().unwrap().status()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn restart_capture() -> Result<(), String> {
+    packet_capture::restart_capture()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_opcode_discovery() -> OpcodeDiscoveryReport {
+    OPCODE_DISCOVERY.lock().unwrap().report()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_opcode_discovery(enabled: bool) {
+    OPCODE_DISCOVERY.lock().unwrap().set_enabled(enabled);
+}
+
+/// Returns the path of the exported JSON file.
+#[tauri::command]
+#[specta::specta]
+pub fn export_opcode_discovery() -> Result<String, String> {
+    let path = OPCODE_DISCOVERY
+        .lock()
+        .map_err(|_| "Failed to lock opcode discovery".to_string())?
+        .export()?;
+    Ok(path.display().to_string())
+}
@@ -1,4 +1,4 @@
-use crate::live::opcodes_models::Encounter;
+use crate::live::opcodes_models::{monster_name, Encounter, MONSTER_UID_CROWDSOURCE_MAP};
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -94,16 +94,16 @@ pub fn save_snapshot(
 }
 
 pub fn snapshot_from_encounter(encounter: &Encounter) -> Option<CrowdsourceMonsterSnapshot> {
+    let monster_id = encounter.current_monster_id()?;
     Some(CrowdsourceMonsterSnapshot {
-        monster_id: encounter.crowdsource_monster_id?,
-        monster_name: encounter.crowdsource_monster_name.clone()?,
-        remote_id: encounter.crowdsource_monster_remote_id.clone()?,
+        monster_id,
+        monster_name: monster_name(monster_id),
+        remote_id: MONSTER_UID_CROWDSOURCE_MAP.get(&monster_id).cloned()?,
     })
 }
 
+/// The saved monster stays pinned until a tracked boss is damaged.
 pub fn apply_snapshot_to_encounter(snapshot: &CrowdsourceMonsterSnapshot, encounter: &mut Encounter) {
-    encounter.crowdsource_monster_id = Some(snapshot.monster_id);
-    encounter.crowdsource_monster_name = Some(snapshot.monster_name.clone());
-    encounter.crowdsource_monster_remote_id = Some(snapshot.remote_id.clone());
+    encounter.pinned_monster_id = Some(snapshot.monster_id);
 }
 
@@ -0,0 +1,267 @@
+use blueprotobuf_lib::blueprotobuf::{CharBaseInfo, CharSerialize, Position, SceneData};
+use std::fmt;
+
+// Every 32-bit word in the buffer is followed by a padding word (0xDEADBEEF in captures).
+const BEGIN_MARKER: u32 = 0xFFFF_FFFE;
+const END_MARKER: u32 = 0xFFFF_FFFD;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirtyDataError {
+    Truncated,
+    MissingBeginMarker(u32),
+    InvalidUtf8,
+}
+
+impl fmt::Display for DirtyDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DirtyDataError::Truncated => write!(f, "truncated dirty data"),
+            DirtyDataError::MissingBeginMarker(word) => {
+                write!(f, "expected a begin marker, found {word:#x}")
+            }
+            DirtyDataError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for DirtyDataError {}
+
+/// Decodes the `BufferStream` of `SyncContainerDirtyData` into a `CharSerialize` holding only
+/// the changed fields. Supports the scene data and the name of the char base, other changed
+/// fields are skipped.
+pub fn decode_char_serialize(buffer: &[u8]) -> Result<CharSerialize, DirtyDataError> {
+    let mut reader = DirtyReader { data: buffer };
+    let mut char_serialize = CharSerialize::default();
+    reader.begin()?;
+    while let Some(field) = reader.next_field()? {
+        match field {
+            2 => read_char_base(&mut reader, char_serialize.char_base.get_or_insert_default())?,
+            3 => read_scene_data(&mut reader, char_serialize.scene_data.get_or_insert_default())?,
+            _ => reader.skip_unknown()?,
+        }
+    }
+    Ok(char_serialize)
+}
+
+fn read_char_base(reader: &mut DirtyReader, char_base: &mut CharBaseInfo) -> Result<(), DirtyDataError> {
+    reader.begin()?;
+    while let Some(field) = reader.next_field()? {
+        match field {
+            4 => char_base.server_id = Some(reader.read_word()?),
+            5 => char_base.name = Some(reader.read_string()?),
+            35 => char_base.fight_point = Some(reader.read_word()? as i32),
+            _ => reader.skip_unknown()?,
+        }
+    }
+    Ok(())
+}
+
+fn read_scene_data(reader: &mut DirtyReader, scene_data: &mut SceneData) -> Result<(), DirtyDataError> {
+    reader.begin()?;
+    while let Some(field) = reader.next_field()? {
+        match field {
+            1 => scene_data.map_id = Some(reader.read_word()?),
+            2 => scene_data.channel_id = Some(reader.read_word()?),
+            3 => read_position(reader, scene_data.pos.get_or_insert_default())?,
+            6 => scene_data.level_map_id = Some(reader.read_word()?),
+            15 => scene_data.line_id = Some(reader.read_word()?),
+            _ => reader.skip_unknown()?,
+        }
+    }
+    Ok(())
+}
+
+fn read_position(reader: &mut DirtyReader, pos: &mut Position) -> Result<(), DirtyDataError> {
+    reader.begin()?;
+    while let Some(field) = reader.next_field()? {
+        match field {
+            1 => pos.x = Some(reader.read_f32()?),
+            2 => pos.y = Some(reader.read_f32()?),
+            3 => pos.z = Some(reader.read_f32()?),
+            4 => pos.dir = Some(reader.read_f32()?),
+            _ => reader.skip_unknown()?,
+        }
+    }
+    Ok(())
+}
+
+struct DirtyReader<'a> {
+    data: &'a [u8],
+}
+
+impl DirtyReader<'_> {
+    fn read_u32(&mut self) -> Result<u32, DirtyDataError> {
+        let (bytes, rest) = self
+            .data
+            .split_first_chunk::<4>()
+            .ok_or(DirtyDataError::Truncated)?;
+        self.data = rest;
+        Ok(u32::from_le_bytes(*bytes))
+    }
+
+    /// Reads a 32-bit value and the padding word after it.
+    fn read_word(&mut self) -> Result<u32, DirtyDataError> {
+        let value = self.read_u32()?;
+        self.read_u32()?;
+        Ok(value)
+    }
+
+    fn read_f32(&mut self) -> Result<f32, DirtyDataError> {
+        Ok(f32::from_bits(self.read_word()?))
+    }
+
+    /// A length word, the UTF-8 bytes and a trailing padding word.
+    fn read_string(&mut self) -> Result<String, DirtyDataError> {
+        let len = self.read_word()? as usize;
+        if self.data.len() < len {
+            return Err(DirtyDataError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        self.read_u32()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DirtyDataError::InvalidUtf8)
+    }
+
+    /// Every message starts with the begin marker followed by a word of unknown meaning.
+    fn begin(&mut self) -> Result<(), DirtyDataError> {
+        let marker = self.read_word()?;
+        if marker != BEGIN_MARKER {
+            return Err(DirtyDataError::MissingBeginMarker(marker));
+        }
+        self.read_word()?;
+        Ok(())
+    }
+
+    /// Skips the value of an unknown field, a message up to its matching end marker and
+    /// anything else as a single word. Values aren't length-prefixed, an unknown string or
+    /// 64-bit value breaks the word alignment and the buffer usually ends up truncated.
+    fn skip_unknown(&mut self) -> Result<(), DirtyDataError> {
+        let marker = self
+            .data
+            .first_chunk::<4>()
+            .map(|bytes| u32::from_le_bytes(*bytes))
+            .ok_or(DirtyDataError::Truncated)?;
+        if marker != BEGIN_MARKER {
+            self.read_word()?;
+            return Ok(());
+        }
+        let mut depth = 0usize;
+        loop {
+            match self.read_word()? {
+                BEGIN_MARKER => depth += 1,
+                END_MARKER => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the next field index, `None` at the end marker of the current message.
+    fn next_field(&mut self) -> Result<Option<u32>, DirtyDataError> {
+        let field = self.read_word()?;
+        Ok((field != END_MARKER).then_some(field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::dirty_data::{decode_char_serialize, DirtyDataError, BEGIN_MARKER, END_MARKER};
+    use blueprotobuf_lib::blueprotobuf;
+    use prost::Message;
+
+    const PAD: u32 = 0xDEAD_BEEF;
+
+    // the three SyncContainerDirtyData in test_add_packet.json, none of them has changed fields
+    const CAPTURED_DIRTY_DATA: [[u8; 28]; 3] = [
+        [
+            0x0a, 0x1a, 0x0a, 0x18, 0xfe, 0xff, 0xff, 0xff, 0xef, 0xbe, 0xad, 0xde, 0x00, 0x00,
+            0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0xfd, 0xff, 0xff, 0xff, 0xef, 0xbe, 0xad, 0xde,
+        ],
+        [
+            0x0a, 0x1a, 0x0a, 0x18, 0xfe, 0xff, 0xff, 0xff, 0xef, 0xbe, 0xad, 0xde, 0x00, 0x00,
+            0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0xfd, 0xff, 0xff, 0xff, 0xef, 0xbe, 0xad, 0xde,
+        ],
+        [
+            0x0a, 0x1a, 0x0a, 0x18, 0xfe, 0xff, 0xff, 0xff, 0xef, 0xbe, 0xad, 0xde, 0x00, 0x00,
+            0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0xfd, 0xff, 0xff, 0xff, 0xef, 0xbe, 0xad, 0xde,
+        ],
+    ];
+
+    fn captured_buffer(message: &[u8]) -> Vec<u8> {
+        let dirty_data = blueprotobuf::SyncContainerDirtyData::decode(message).unwrap();
+        dirty_data.v_data.unwrap().buffer.unwrap()
+    }
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| [*word, PAD]).flat_map(u32::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_decode_captured() {
+        for message in CAPTURED_DIRTY_DATA {
+            let char_serialize = decode_char_serialize(&captured_buffer(&message)).unwrap();
+            assert_eq!(char_serialize, Default::default());
+        }
+    }
+
+    #[test]
+    fn test_decode_scene_data_and_name() {
+        let mut buffer = words(&[BEGIN_MARKER, 0, 3, BEGIN_MARKER, 0, 15, 7, 3, BEGIN_MARKER, 0, 1]);
+        buffer.extend(words(&[1.5f32.to_bits(), 2, (-4.0f32).to_bits(), END_MARKER, END_MARKER]));
+        buffer.extend(words(&[2, BEGIN_MARKER, 0, 5, 4]));
+        buffer.extend(b"Ayla");
+        buffer.extend(PAD.to_le_bytes());
+        buffer.extend(words(&[END_MARKER, END_MARKER]));
+
+        let char_serialize = decode_char_serialize(&buffer).unwrap();
+        let scene_data = char_serialize.scene_data.unwrap();
+        assert_eq!(scene_data.line_id, Some(7));
+        assert_eq!(scene_data.map_id, None);
+        let pos = scene_data.pos.unwrap();
+        assert_eq!((pos.x, pos.y, pos.z), (Some(1.5), Some(-4.0), None));
+        assert_eq!(char_serialize.char_base.unwrap().name.as_deref(), Some("Ayla"));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let captured = captured_buffer(&CAPTURED_DIRTY_DATA[0]);
+        assert_eq!(decode_char_serialize(&captured[..20]), Err(DirtyDataError::Truncated));
+        assert_eq!(decode_char_serialize(&words(&[0])), Err(DirtyDataError::MissingBeginMarker(0)));
+        // an unknown scalar as the last word
+        assert_eq!(
+            decode_char_serialize(&words(&[BEGIN_MARKER, 0, 7])),
+            Err(DirtyDataError::Truncated)
+        );
+        // an unknown message that never ends
+        assert_eq!(
+            decode_char_serialize(&words(&[BEGIN_MARKER, 0, 7, BEGIN_MARKER, 0, 1, 2])),
+            Err(DirtyDataError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields_are_skipped() {
+        // an unknown CharSerialize field holding a nested message, then the scene data
+        let mut buffer = words(&[BEGIN_MARKER, 0, 9, BEGIN_MARKER, 0, 1, 5, 2, BEGIN_MARKER, 0]);
+        buffer.extend(words(&[1, 6, END_MARKER, END_MARKER]));
+        // an unknown SceneData message and scalar around the line
+        buffer.extend(words(&[3, BEGIN_MARKER, 0, 12, BEGIN_MARKER, 0, END_MARKER, 15, 4, 21, 9]));
+        buffer.extend(words(&[END_MARKER]));
+        // the team isn't decoded, then an unknown CharBaseInfo scalar before the name
+        buffer.extend(words(&[2, BEGIN_MARKER, 0, 20, BEGIN_MARKER, 0, 1, 42, 0, END_MARKER]));
+        buffer.extend(words(&[36, 7, 5, 4]));
+        buffer.extend(b"Ayla");
+        buffer.extend(PAD.to_le_bytes());
+        buffer.extend(words(&[END_MARKER, END_MARKER]));
+
+        let char_serialize = decode_char_serialize(&buffer).unwrap();
+        assert_eq!(char_serialize.scene_data.unwrap().line_id, Some(4));
+        let char_base = char_serialize.char_base.unwrap();
+        assert_eq!(char_base.name.as_deref(), Some("Ayla"));
+        assert_eq!(char_base.team_info, None);
+    }
+}
@@ -1,159 +1,93 @@
 use crate::live::crowdsource_persistence::{
     apply_snapshot_to_encounter, load_snapshot, save_snapshot, snapshot_from_encounter,
 };
+use crate::live::message_journal::MESSAGE_JOURNAL;
 use crate::live::opcodes_models::EncounterMutex;
 use crate::live::opcodes_process::{
-    on_server_change, process_aoi_sync_delta, process_sync_container_data,
-    process_sync_near_entities, process_sync_to_me_delta_info,
+    evict_stale_entities, now_ms, on_hp_report_sent, process_notify, send_hp_report,
+    HP_REPORT_DRY_RUN,
 };
 use crate::packets;
-use blueprotobuf_lib::blueprotobuf;
-use bytes::Bytes;
-use log::{info, warn};
-use prost::Message;
+use crate::packets::capture_source::CaptureBackend;
+use crate::packets::capture_stats::CAPTURE_STATS;
+use crate::packets::utils::StreamDirection;
+use blueprotobuf_lib::wire;
+use log::{debug, error, info, warn};
+use std::sync::atomic::Ordering;
 use tauri::{AppHandle, Manager};
 
 pub async fn start(app_handle: AppHandle) {
-    let mut rx = packets::packet_capture::start_capture();
+    let backend = CaptureBackend::from_env();
+    // a recording would send its old HP and deaths to bptimer again
+    if matches!(backend, CaptureBackend::Replay { .. }) {
+        info!("Replaying {backend:?}, HP reports won't be sent");
+        HP_REPORT_DRY_RUN.store(true, Ordering::Relaxed);
+    }
+    let mut rx = packets::packet_capture::start_capture(backend);
 
-    while let Some((op, data)) = rx.recv().await {
-        {
-            let state = app_handle.state::<EncounterMutex>();
-            let encounter = state.lock().unwrap();
-            if encounter.is_encounter_paused {
-                info!("packet dropped due to encounter paused");
-                continue;
-            }
+    while let Some((direction, op, data)) = rx.recv().await {
+        MESSAGE_JOURNAL.lock().unwrap().record(direction, &op, &data);
+        // only what the server sends changes the encounter
+        if direction == StreamDirection::Upstream {
+            debug!("Client sent {op:?} ({} bytes)", data.len());
+            continue;
+        }
+        if let packets::opcodes::Pkt::Return { stub_id, call } = op {
+            debug!("Return for stub {stub_id} of {call:?} ({} bytes)", data.len());
         }
-        match op {
-            packets::opcodes::Pkt::ServerChangeInfo => {
-                let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                on_server_change(&mut encounter_state);
 
-                if let Some(snapshot) = load_snapshot(&app_handle) {
-                    apply_snapshot_to_encounter(&snapshot, &mut encounter_state);
-                }
-            }
-            packets::opcodes::Pkt::SyncNearEntities => {
-                let sync_near_entities =
-                    match blueprotobuf::SyncNearEntities::decode(Bytes::from(data)) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            warn!("Error decoding SyncNearEntities.. ignoring: {e}");
-                            continue;
-                        }
-                    };
-                let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                let previous_remote_id = encounter_state.crowdsource_monster_remote_id.clone();
-                let mut snapshot_to_save = None;
-                if process_sync_near_entities(&mut encounter_state, sync_near_entities).is_none() {
-                    warn!("Error processing SyncNearEntities.. ignoring.");
-                }
-                if encounter_state.crowdsource_monster_remote_id != previous_remote_id {
-                    snapshot_to_save = snapshot_from_encounter(&encounter_state);
-                }
-                drop(encounter_state);
-                if let Some(snapshot) = snapshot_to_save {
-                    if let Err(err) = save_snapshot(&app_handle, &snapshot) {
-                        warn!("live_main::start - failed to persist crowdsourced monster snapshot: {err}");
-                    }
-                }
-            }
-            packets::opcodes::Pkt::SyncContainerData => {
-                let sync_container_data =
-                    match blueprotobuf::SyncContainerData::decode(Bytes::from(data)) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            warn!("Error decoding SyncContainerData.. ignoring: {e}");
-                            continue;
-                        }
-                    };
-                let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                let previous_remote_id = encounter_state.crowdsource_monster_remote_id.clone();
-                encounter_state.local_player = Some(sync_container_data.clone());
-                if process_sync_container_data(&mut encounter_state, sync_container_data).is_none() {
-                    warn!("Error processing SyncContainerData.. ignoring.");
-                }
-                let mut snapshot_to_save = None;
-                if encounter_state.crowdsource_monster_remote_id != previous_remote_id {
-                    snapshot_to_save = snapshot_from_encounter(&encounter_state);
-                }
-                drop(encounter_state);
-                if let Some(snapshot) = snapshot_to_save {
-                    if let Err(err) = save_snapshot(&app_handle, &snapshot) {
-                        warn!("live_main::start - failed to persist crowdsourced monster snapshot: {err}");
-                    }
-                }
-            }
-            packets::opcodes::Pkt::SyncServerTime => {
-                let _sync_server_time =
-                    match blueprotobuf::SyncServerTime::decode(Bytes::from(data)) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            warn!("Error decoding SyncServerTime.. ignoring: {e}");
-                            continue;
-                        }
-                    };
+        let encounter_state = app_handle.state::<EncounterMutex>();
+        let mut encounter_state = encounter_state.lock().unwrap();
+        if encounter_state.is_encounter_paused {
+            info!("packet dropped due to encounter paused");
+            continue;
+        }
+        let previous_monster_id = encounter_state.current_monster_id();
+        if let Err(e) = process_notify(&mut encounter_state, op, data.clone()) {
+            on_decode_failure(op, &data, &e);
+        }
+        let mut snapshot_to_save = None;
+        if op == packets::opcodes::Pkt::ServerChangeInfo {
+            if let Some(snapshot) = load_snapshot(&app_handle) {
+                apply_snapshot_to_encounter(&snapshot, &mut encounter_state);
             }
-            packets::opcodes::Pkt::SyncToMeDeltaInfo => {
-                let sync_to_me_delta_info =
-                    match blueprotobuf::SyncToMeDeltaInfo::decode(Bytes::from(data)) {
-                        Ok(sync_to_me_delta_info) => sync_to_me_delta_info,
-                        Err(e) => {
-                            warn!("Error decoding SyncToMeDeltaInfo.. ignoring: {e}");
-                            continue;
-                        }
-                    };
-                let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                let previous_remote_id = encounter_state.crowdsource_monster_remote_id.clone();
-                let mut snapshot_to_save = None;
-                if process_sync_to_me_delta_info(&mut encounter_state, sync_to_me_delta_info).is_none() {
-                    warn!("Error processing SyncToMeDeltaInfo.. ignoring.");
-                }
-                if encounter_state.crowdsource_monster_remote_id != previous_remote_id {
-                    snapshot_to_save = snapshot_from_encounter(&encounter_state);
-                }
-                drop(encounter_state);
-                if let Some(snapshot) = snapshot_to_save {
-                    if let Err(err) = save_snapshot(&app_handle, &snapshot) {
-                        warn!("live_main::start - failed to persist crowdsourced monster snapshot: {err}");
-                    }
+        } else if encounter_state.current_monster_id() != previous_monster_id {
+            snapshot_to_save = snapshot_from_encounter(&encounter_state);
+        }
+
+        evict_stale_entities(&mut encounter_state, now_ms());
+        for report in std::mem::take(&mut encounter_state.pending_reports) {
+            let app_handle = app_handle.clone();
+            tokio::spawn(async move {
+                let result = send_hp_report(&report).await;
+                if let Err(e) = &result {
+                    error!("{e}: {report:?}");
                 }
-            }
-            packets::opcodes::Pkt::SyncNearDeltaInfo => {
-                let sync_near_delta_info =
-                    match blueprotobuf::SyncNearDeltaInfo::decode(Bytes::from(data)) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            warn!("Error decoding SyncNearDeltaInfo.. ignoring: {e}");
-                            continue;
-                        }
-                    };
                 let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                let mut snapshot_to_save = None;
-                for aoi_sync_delta in sync_near_delta_info.delta_infos {
-                    let previous_remote_id = encounter_state.crowdsource_monster_remote_id.clone();
-                    if process_aoi_sync_delta(&mut encounter_state, aoi_sync_delta).is_none() {
-                        warn!("Error processing SyncToMeDeltaInfo.. ignoring.");
-                        continue;
-                    }
+                on_hp_report_sent(&mut encounter_state.lock().unwrap(), &report, result.is_ok());
+            });
+        }
+        drop(encounter_state);
 
-                    if encounter_state.crowdsource_monster_remote_id != previous_remote_id {
-                        snapshot_to_save = snapshot_from_encounter(&encounter_state);
-                    }
-                }
-                drop(encounter_state);
-                if let Some(snapshot) = snapshot_to_save {
-                    if let Err(err) = save_snapshot(&app_handle, &snapshot) {
-                        warn!("live_main::start - failed to persist crowdsourced monster snapshot: {err}");
-                    }
-                }
+        if let Some(snapshot) = snapshot_to_save {
+            if let Err(err) = save_snapshot(&app_handle, &snapshot) {
+                warn!("live_main::start - failed to persist crowdsourced monster snapshot: {err}");
             }
         }
     }
 }
+
+/// Logs a payload that doesn't match its message type, so schema changes after a game
+/// patch can be worked out from the logs.
+fn on_decode_failure(op: packets::opcodes::Pkt, data: &[u8], e: &prost::DecodeError) {
+    const MAX_DUMP_BYTES: usize = 2048;
+    warn!("Error decoding {} ({} bytes).. ignoring: {e}", op.name(), data.len());
+    if log::log_enabled!(log::Level::Debug) {
+        debug!(
+            "Undecodable {} payload:\n{}",
+            op.name(),
+            wire::dump(&data[..data.len().min(MAX_DUMP_BYTES)])
+        );
+    }
+    CAPTURE_STATS.lock().unwrap().record_decode_failure(op.name());
+}
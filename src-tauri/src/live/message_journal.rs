@@ -0,0 +1,207 @@
+use crate::packets::opcodes::{NotifyHook, NotifyMessage, Pkt};
+use crate::packets::utils::{StreamDirection, remove_old_files};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use specta::Type;
+use std::fs::{self, File};
+use std::io::{LineWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024; // 64 mb
+const MAX_FILES: usize = 10;
+const FILE_PREFIX: &str = "journal-";
+const FILE_EXTENSION: &str = "jsonl";
+
+pub static MESSAGE_JOURNAL: Lazy<Mutex<MessageJournal>> =
+    Lazy::new(|| Mutex::new(MessageJournal::default()));
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+pub struct MessageJournalSettings {
+    pub enabled: bool,
+    /// Opcode names to journal, e.g. `SyncNearEntities`. Empty journals every opcode.
+    pub include: Vec<String>,
+    /// Opcode names to leave out, applied after `include`.
+    pub exclude: Vec<String>,
+}
+
+impl MessageJournalSettings {
+    fn allows(&self, pkt_name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|name| name == pkt_name))
+            && !self.exclude.iter().any(|name| name == pkt_name)
+    }
+}
+
+/// Writes every decoded game message as a timestamped JSON line, to rotating files like
+/// the session recorder.
+///
+/// Messages are journaled as JSON objects with the default `journal-json` feature, without
+/// it the `message` field holds their debug format as a string.
+#[derive(Default)]
+pub struct MessageJournal {
+    directory: Option<PathBuf>,
+    settings: MessageJournalSettings,
+    writer: Option<LineWriter<File>>,
+    bytes_written: u64,
+}
+
+impl MessageJournal {
+    pub fn configure(&mut self, directory: PathBuf) {
+        self.directory = Some(directory);
+    }
+
+    pub fn settings(&self) -> &MessageJournalSettings {
+        &self.settings
+    }
+
+    pub fn apply_settings(&mut self, settings: MessageJournalSettings) -> Result<(), String> {
+        if settings.enabled && self.writer.is_none() {
+            self.writer = Some(self.open()?);
+        } else if !settings.enabled {
+            self.writer = None;
+        }
+        info!(
+            "Message journal {} (include: {:?}, exclude: {:?})",
+            if settings.enabled { "enabled" } else { "disabled" },
+            settings.include,
+            settings.exclude
+        );
+        self.settings = settings;
+        Ok(())
+    }
+
+    pub fn record(&mut self, direction: StreamDirection, pkt: &Pkt, data: &[u8]) {
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        let pkt_name = pkt.name();
+        if !self.settings.allows(pkt_name) {
+            return;
+        }
+
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let message = decode_message(pkt, data).unwrap_or_else(|e| {
+            serde_json::json!({
+                "decode_error": e,
+                "raw_len": data.len(),
+            })
+        });
+        let rpc = match pkt {
+            Pkt::Call(call) => Some(*call),
+            Pkt::Return { call, .. } => *call,
+            _ => None,
+        };
+        let line = serde_json::json!({
+            "timestamp_ms": timestamp_ms,
+            "direction": format!("{direction:?}"),
+            "pkt": pkt_name,
+            "rpc": rpc.map(|call| serde_json::json!({
+                "service_uuid": call.service_uuid,
+                "stub_id": call.stub_id,
+                "method_id": call.method_id,
+            })),
+            "message": message,
+        })
+        .to_string();
+        if let Err(e) = writeln!(writer, "{line}") {
+            warn!("Failed to write to message journal, disabling it: {e}");
+            self.writer = None;
+            self.settings.enabled = false;
+            return;
+        }
+        self.bytes_written += line.len() as u64 + 1;
+        if self.bytes_written >= MAX_FILE_SIZE {
+            match self.open() {
+                Ok(writer) => self.writer = Some(writer),
+                Err(e) => {
+                    warn!("Failed to rotate message journal, disabling it: {e}");
+                    self.writer = None;
+                    self.settings.enabled = false;
+                }
+            }
+        }
+    }
+
+    fn open(&mut self) -> Result<LineWriter<File>, String> {
+        let directory = self
+            .directory
+            .as_ref()
+            .ok_or_else(|| "Message journal has no output directory".to_string())?;
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create {}: {e}", directory.display()))?;
+        remove_old_files(directory, FILE_PREFIX, FILE_EXTENSION, MAX_FILES);
+        let path = directory.join(format!(
+            "{FILE_PREFIX}{}.{FILE_EXTENSION}",
+            chrono::Local::now().format("%Y-%m-%d_%H-%M-%S%.3f")
+        ));
+        let file = File::create(&path)
+            .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+        info!("Writing message journal to {}", path.display());
+        self.bytes_written = 0;
+        Ok(LineWriter::new(file))
+    }
+}
+
+struct JsonHook;
+
+impl NotifyHook for JsonHook {
+    type Output = Result<Value, String>;
+
+    #[cfg(feature = "journal-json")]
+    fn on_message<M: NotifyMessage>(&mut self, message: M) -> Self::Output {
+        serde_json::to_value(message).map_err(|e| e.to_string())
+    }
+
+    // without the serde impls of `blueprotobuf` the message is journaled in its debug format
+    #[cfg(not(feature = "journal-json"))]
+    fn on_message<M: NotifyMessage>(&mut self, message: M) -> Self::Output {
+        Ok(Value::String(format!("{message:?}")))
+    }
+}
+
+fn decode_message(pkt: &Pkt, data: &[u8]) -> Result<Value, String> {
+    match pkt {
+        Pkt::ServerChangeInfo => Ok(Value::Null),
+        // request/response schemas aren't mapped yet, keep the raw bytes
+        Pkt::Call(_) | Pkt::Return { .. } => Ok(serde_json::json!({
+            "raw_hex": data.iter().map(|b| format!("{b:02x}")).collect::<String>(),
+        })),
+        _ => match pkt.decode_notify(data, &mut JsonHook) {
+            Some(Ok(message)) => message,
+            Some(Err(e)) => Err(e.to_string()),
+            None => Err(format!("{} has no message type", pkt.name())),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::message_journal::MessageJournalSettings;
+
+    fn settings(include: &[&str], exclude: &[&str]) -> MessageJournalSettings {
+        MessageJournalSettings {
+            enabled: true,
+            include: include.iter().map(|name| (*name).to_string()).collect(),
+            exclude: exclude.iter().map(|name| (*name).to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_journal_filters() {
+        assert!(settings(&[], &[]).allows("SyncNearEntities"));
+        let include = settings(&["SyncNearEntities", "Return"], &[]);
+        assert!(include.allows("Return"));
+        assert!(!include.allows("SyncServerTime"));
+        let exclude = settings(&[], &["SyncServerTime"]);
+        assert!(exclude.allows("SyncNearEntities"));
+        assert!(!exclude.allows("SyncServerTime"));
+        // exclude wins over include
+        assert!(!settings(&["SyncServerTime"], &["SyncServerTime"]).allows("SyncServerTime"));
+    }
+}
@@ -1,10 +1,24 @@
-use blueprotobuf_lib::blueprotobuf::{EEntityType, SyncContainerData};
+use blueprotobuf_lib::blueprotobuf::{EDisappearType, EEntityType, SceneData, Vector3};
+use log::info;
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use serde::Serialize;
+use specta::Type;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Mutex;
 
 pub type EncounterMutex = Mutex<Encounter>;
 
+/// Entities without updates for this long are dropped, the server doesn't always send a
+/// disappear for entities left behind by a teleport or line change.
+pub const ENTITY_TTL_MS: u128 = 120_000;
+pub const ENTITY_EVICTION_INTERVAL_MS: u128 = 5_000;
+/// Tracked bosses are kept this long after leaving view.
+pub const TRACKED_BOSS_TTL_MS: u128 = 300_000;
+const MAX_HP_HISTORY: usize = 64;
+const HP_REPORT_STEP_PCT: i64 = 5;
+/// A boss that disappears at or below this HP% without a death reason is assumed dead.
+pub const LOW_HP_DEATH_PCT: i64 = 2;
+
 #[derive(Debug, Default, Clone)]
 pub struct Encounter {
     pub is_encounter_paused: bool,
@@ -12,10 +26,184 @@ pub struct Encounter {
     pub time_fight_start_ms: u128,
     pub local_player_uid: Option<i64>,
     pub entity_uid_to_entity: HashMap<i64, Entity>,
-    pub local_player: Option<SyncContainerData>,
-    pub crowdsource_monster_name: Option<String>,
-    pub crowdsource_monster_id: Option<i32>,
-    pub crowdsource_monster_remote_id: Option<String>,
+    pub local_player: LocalPlayerState,
+    /// Crowdsourced monsters seen this session keyed by entity uid, each with its own reports.
+    pub tracked_bosses: HashMap<i64, TrackedBoss>,
+    /// The monster picked in the overlay, until a tracked boss is damaged.
+    pub pinned_monster_id: Option<i32>,
+    /// The tracked boss damaged last.
+    pub last_damaged_uid: Option<i64>,
+    /// Reports waiting to be sent by `live_main`, outside of the encounter lock.
+    pub pending_reports: Vec<HpReport>,
+    pub time_last_eviction_ms: u128,
+}
+
+impl Encounter {
+    /// The monster the overlay follows, the one picked by the user or else the tracked boss
+    /// damaged last.
+    pub fn current_monster_id(&self) -> Option<i32> {
+        self.pinned_monster_id.or_else(|| {
+            let tracked_boss = self.tracked_bosses.get(&self.last_damaged_uid?)?;
+            Some(tracked_boss.monster_id)
+        })
+    }
+
+    /// Uid of the tracked boss with `monster_id` on `line_id`, the one in view or else the one
+    /// seen last.
+    pub fn tracked_boss_uid(&self, monster_id: i32, line_id: u32) -> Option<i64> {
+        self.tracked_bosses
+            .iter()
+            .filter(|(_, tracked_boss)| {
+                tracked_boss.monster_id == monster_id && tracked_boss.line_id == Some(line_id)
+            })
+            .max_by_key(|(_, tracked_boss)| (tracked_boss.in_view(), tracked_boss.last_seen_ms))
+            .map(|(uid, _)| *uid)
+    }
+}
+
+/// Why a tracked boss is no longer in view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+pub enum LeftViewReason {
+    Dead,
+    /// Walked out of range, or despawned without a reason.
+    Despawned,
+    Destroyed,
+    /// The local player changed scene or line.
+    Transferred,
+    /// No update within `ENTITY_TTL_MS`.
+    Expired,
+}
+
+impl From<EDisappearType> for LeftViewReason {
+    fn from(disappear_type: EDisappearType) -> Self {
+        match disappear_type {
+            EDisappearType::EDisappearNormal => LeftViewReason::Despawned,
+            EDisappearType::EDisappearDead => LeftViewReason::Dead,
+            EDisappearType::EDisappearDestroy => LeftViewReason::Destroyed,
+            EDisappearType::EDisappearTransferLeave
+            | EDisappearType::EDisappearTransferPassLineLeave => LeftViewReason::Transferred,
+        }
+    }
+}
+
+/// An HP report for bptimer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HpReport {
+    /// The tracked boss reported, `None` when the shortcut reports a boss that wasn't seen.
+    pub uid: Option<i64>,
+    pub monster_id: i32,
+    pub hp_pct: i64,
+    pub line: u32,
+    pub pos_x: f32,
+    pub pos_y: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Type)]
+pub struct HpSample {
+    pub timestamp_ms: u64,
+    pub hp: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackedBoss {
+    pub monster_id: i32,
+    pub name: String,
+    pub remote_id: Option<String>,
+    /// Line of the local player when the boss was last seen.
+    pub line_id: Option<u32>,
+    /// Last known position of the boss, kept after it leaves view.
+    pub pos: Option<EntityPosition>,
+    pub max_hp: Option<i64>,
+    pub hp_history: VecDeque<HpSample>,
+    pub last_reported_hp_pct: Option<i64>,
+    /// Set once the 0% report was sent, automatically or with the shortcut.
+    pub death_reported: bool,
+    /// While the 0% report is being sent, so other death signals don't send it again.
+    pub death_report_in_flight: bool,
+    /// `None` while the boss is in view.
+    pub left_view: Option<LeftViewReason>,
+    pub last_seen_ms: u128,
+}
+
+impl TrackedBoss {
+    pub fn new(monster_id: i32) -> Self {
+        Self {
+            monster_id,
+            name: monster_name(monster_id),
+            remote_id: MONSTER_UID_CROWDSOURCE_MAP.get(&monster_id).cloned(),
+            line_id: None,
+            pos: None,
+            max_hp: None,
+            hp_history: VecDeque::new(),
+            last_reported_hp_pct: None,
+            death_reported: false,
+            death_report_in_flight: false,
+            left_view: None,
+            last_seen_ms: 0,
+        }
+    }
+
+    pub fn in_view(&self) -> bool {
+        self.left_view.is_none()
+    }
+
+    pub fn curr_hp(&self) -> Option<i64> {
+        self.hp_history.back().map(|sample| sample.hp)
+    }
+
+    /// 0% only once the HP reached 0, a living boss is at least at 1%.
+    pub fn hp_pct(&self) -> Option<i64> {
+        let max_hp = self.max_hp.filter(|max_hp| *max_hp > 0)?;
+        let curr_hp = self.curr_hp()?;
+        let min_pct = if curr_hp > 0 { 1 } else { 0 };
+        Some((curr_hp * 100 / max_hp).clamp(min_pct, 100))
+    }
+
+    /// Returns `false` if the 0% report was already sent or is being sent.
+    pub fn start_death_report(&mut self) -> bool {
+        if self.death_reported || self.death_report_in_flight {
+            return false;
+        }
+        self.death_report_in_flight = true;
+        true
+    }
+
+    /// A 0% report that failed is sent again on the next death signal.
+    pub fn finish_death_report(&mut self, sent: bool) {
+        self.death_report_in_flight = false;
+        self.death_reported |= sent;
+    }
+
+    pub fn record_hp(&mut self, hp: i64, timestamp_ms: u128) {
+        if self.hp_history.len() == MAX_HP_HISTORY {
+            self.hp_history.pop_front();
+        }
+        self.hp_history.push_back(HpSample {
+            timestamp_ms: timestamp_ms as u64,
+            hp,
+        });
+    }
+
+    /// Returns the HP% to report and marks it as reported: the first known value, then
+    /// every time the HP reaches or passes a multiple of 5% since the last report.
+    pub fn take_hp_report(&mut self) -> Option<i64> {
+        let hp_pct = self.hp_pct()?;
+        let step = |pct: i64| (pct + HP_REPORT_STEP_PCT - 1) / HP_REPORT_STEP_PCT;
+        let should_report = self
+            .last_reported_hp_pct
+            .is_none_or(|last_pct| step(last_pct) != step(hp_pct));
+        if !should_report {
+            return None;
+        }
+        self.last_reported_hp_pct = Some(hp_pct);
+        Some(hp_pct)
+    }
+}
+
+/// Entity types whose state is kept in `Encounter::entity_uid_to_entity`. NPCs, bullets,
+/// drops and the other types are ignored.
+pub fn is_tracked_entity_type(entity_type: EEntityType) -> bool {
+    matches!(entity_type, EEntityType::EntChar | EEntityType::EntMonster)
 }
 
 #[derive(Debug, Default, Clone)]
@@ -23,8 +211,73 @@ pub struct Entity {
     pub entity_type: EEntityType,
     pub name: Option<String>, 
     pub monster_id: Option<i32>,
-    pub curr_hp: Option<i32>, 
-    pub max_hp: Option<i32>,
+    pub curr_hp: Option<i64>,
+    pub max_hp: Option<i64>,
+    pub pos: Option<EntityPosition>,
+    pub last_seen_ms: u128,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EntityPosition {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl From<Vector3> for EntityPosition {
+    fn from(vector: Vector3) -> Self {
+        Self {
+            x: vector.x.unwrap_or_default(),
+            y: vector.y.unwrap_or_default(),
+            z: vector.z.unwrap_or_default(),
+        }
+    }
+}
+
+/// The parts of the local player reports depend on, updated from full and dirty container
+/// syncs and from its own delta updates.
+#[derive(Debug, Default, Clone)]
+pub struct LocalPlayerState {
+    pub map_id: Option<u32>,
+    pub line_id: Option<u32>,
+    pub pos: Option<EntityPosition>,
+}
+
+impl LocalPlayerState {
+    /// Takes the fields set in `scene_data`, keeping the current value of the others.
+    pub fn apply_scene_data(&mut self, scene_data: &SceneData) {
+        if let Some(map_id) = scene_data.map_id {
+            // a position on the previous map means nothing on this one
+            if self.map_id.is_some_and(|current| current != map_id) {
+                self.pos = None;
+            }
+            self.map_id = Some(map_id);
+        }
+        if let Some(line_id) = scene_data.line_id {
+            if self.line_id.is_some_and(|current| current != line_id) {
+                info!("Local player moved from line {:?} to line {line_id}", self.line_id);
+            }
+            self.line_id = Some(line_id);
+        }
+        if let Some(pos) = &scene_data.pos {
+            // coordinates missing from a dirty update are unchanged, or still unknown
+            let current = self.pos;
+            if let (Some(x), Some(y), Some(z)) = (
+                pos.x.or(current.map(|current| current.x)),
+                pos.y.or(current.map(|current| current.y)),
+                pos.z.or(current.map(|current| current.z)),
+            ) {
+                self.pos = Some(EntityPosition { x, y, z });
+            }
+        }
+    }
+}
+
+impl Entity {
+    pub fn is_crowdsourced(&self) -> bool {
+        self.monster_id
+            .is_some_and(|id| MONSTER_NAMES_CROWDSOURCE.contains_key(&id))
+    }
 }
 
 pub static MONSTER_NAMES: Lazy<HashMap<i32, String>> = Lazy::new(|| {
@@ -87,6 +340,14 @@ static MONSTER_REMOTE_LOOKUP: Lazy<HashMap<String, (i32, String)>> = Lazy::new(|
     map
 });
 
+pub fn monster_name(monster_id: i32) -> String {
+    MONSTER_NAMES
+        .get(&monster_id)
+        .or_else(|| MONSTER_NAMES_CROWDSOURCE.get(&monster_id))
+        .cloned()
+        .unwrap_or_else(|| format!("Monster {monster_id}"))
+}
+
 pub fn get_crowdsource_monster_choices() -> Vec<(i32, String, String)> {
     let mut choices: Vec<(i32, String, String)> = MONSTER_REMOTE_LOOKUP
         .iter()
@@ -109,3 +370,113 @@ pub mod attr_type {
     pub const ATTR_HP: i32 = 0x2c2e;
     pub const ATTR_MAX_HP: i32 = 0x2c38;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::live::opcodes_models::{
+        Encounter, EntityPosition, LeftViewReason, LocalPlayerState, TrackedBoss,
+    };
+    use blueprotobuf_lib::blueprotobuf::{Position, SceneData};
+
+    #[test]
+    fn test_hp_reports_follow_5_pct_steps() {
+        let mut tracked_boss = TrackedBoss::new(0);
+        tracked_boss.max_hp = Some(1000);
+        let mut reports = Vec::new();
+        for hp in [970, 960, 950, 940, 890, 880, 5, 0] {
+            tracked_boss.record_hp(hp, 0);
+            reports.extend(tracked_boss.take_hp_report());
+        }
+        assert_eq!(reports, vec![97, 95, 89, 1, 0]);
+    }
+
+    #[test]
+    fn test_death_is_reported_once_sent() {
+        let mut tracked_boss = TrackedBoss::new(0);
+        assert!(tracked_boss.start_death_report());
+        assert!(!tracked_boss.start_death_report());
+        // failed, the next death signal tries again
+        tracked_boss.finish_death_report(false);
+        assert!(!tracked_boss.death_reported);
+        assert!(tracked_boss.start_death_report());
+        tracked_boss.finish_death_report(true);
+        assert!(tracked_boss.death_reported);
+        assert!(!tracked_boss.start_death_report());
+    }
+
+    #[test]
+    fn test_scene_data_updates_are_merged() {
+        let mut local_player = LocalPlayerState::default();
+        local_player.apply_scene_data(&SceneData {
+            map_id: Some(7),
+            line_id: Some(3),
+            pos: Some(Position {
+                x: Some(1.0),
+                y: Some(2.0),
+                z: Some(3.0),
+                dir: None,
+            }),
+            ..Default::default()
+        });
+        assert_eq!((local_player.map_id, local_player.line_id), (Some(7), Some(3)));
+
+        // dirty updates only carry the changed fields
+        local_player.apply_scene_data(&SceneData {
+            line_id: Some(5),
+            ..Default::default()
+        });
+        local_player.apply_scene_data(&SceneData {
+            pos: Some(Position {
+                y: Some(4.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert_eq!((local_player.map_id, local_player.line_id), (Some(7), Some(5)));
+        assert_eq!(local_player.pos, Some(EntityPosition { x: 1.0, y: 4.0, z: 3.0 }));
+
+        // the position is unknown after a map change that didn't carry it
+        local_player.apply_scene_data(&SceneData {
+            map_id: Some(8),
+            ..Default::default()
+        });
+        assert_eq!(local_player.pos, None);
+        let mut scene_data = SceneData {
+            pos: Some(Position {
+                x: Some(5.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        local_player.apply_scene_data(&scene_data);
+        assert_eq!(local_player.pos, None);
+        scene_data.pos = Some(Position {
+            x: Some(5.0),
+            y: Some(6.0),
+            z: Some(7.0),
+            dir: None,
+        });
+        local_player.apply_scene_data(&scene_data);
+        assert_eq!(local_player.pos, Some(EntityPosition { x: 5.0, y: 6.0, z: 7.0 }));
+    }
+
+    #[test]
+    fn test_tracked_boss_uid_prefers_the_boss_in_view() {
+        let boss = |line_id: u32, left_view: Option<LeftViewReason>, last_seen_ms: u128| TrackedBoss {
+            line_id: Some(line_id),
+            left_view,
+            last_seen_ms,
+            ..TrackedBoss::new(10032)
+        };
+        let mut encounter = Encounter::default();
+        encounter.tracked_bosses.insert(1, boss(3, Some(LeftViewReason::Dead), 300));
+        encounter.tracked_bosses.insert(2, boss(3, None, 100));
+        encounter.tracked_bosses.insert(3, boss(5, Some(LeftViewReason::Despawned), 200));
+        encounter.tracked_bosses.insert(4, boss(5, Some(LeftViewReason::Expired), 100));
+
+        assert_eq!(encounter.tracked_boss_uid(10032, 3), Some(2));
+        assert_eq!(encounter.tracked_boss_uid(10032, 5), Some(3));
+        assert_eq!(encounter.tracked_boss_uid(10032, 7), None);
+        assert_eq!(encounter.tracked_boss_uid(10009, 3), None);
+    }
+}
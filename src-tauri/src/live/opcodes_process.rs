@@ -1,39 +1,199 @@
+use crate::live::attr_codec::{decode_attr, AttrError, AttrValue};
 use crate::live::bptimer_stream::{
     BPTIMER_BASE_URL, CREATE_HP_REPORT_ENDPOINT, CROWD_SOURCE_API_KEY,
 };
+use crate::live::dirty_data::decode_char_serialize;
 use crate::live::opcodes_models::{
-    attr_type, Encounter, Entity, MONSTER_NAMES, MONSTER_NAMES_CROWDSOURCE,
-    MONSTER_UID_CROWDSOURCE_MAP,
+    attr_type, is_tracked_entity_type, Encounter, Entity, EntityPosition, HpReport, LeftViewReason, LocalPlayerState, TrackedBoss, ENTITY_EVICTION_INTERVAL_MS, ENTITY_TTL_MS,
+    LOW_HP_DEATH_PCT, TRACKED_BOSS_TTL_MS,
 };
-use crate::packets::utils::BinaryReader;
+use crate::packets::opcodes::{NotifyHandler, Pkt};
 use blueprotobuf_lib::blueprotobuf;
-use log::{error, info, warn};
+use bytes::Bytes;
+use log::{debug, info, warn};
+use std::collections::HashMap;
 use std::default::Default;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Set while a recording is replayed, its reports are logged instead of sent to bptimer.
+pub static HP_REPORT_DRY_RUN: AtomicBool = AtomicBool::new(false);
+
 pub fn on_server_change(encounter: &mut Encounter) {
     info!("on server change");
     encounter.clone_from(&Encounter::default());
 }
 
+pub fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis()
+}
+
+/// Drops an entity. A tracked boss is kept with the reason it left view, for the reports
+/// and the overlay.
+fn remove_entity(encounter: &mut Encounter, uid: i64, reason: LeftViewReason) {
+    let Some(entity) = encounter.entity_uid_to_entity.remove(&uid) else {
+        return;
+    };
+    let Some(tracked_boss) = encounter.tracked_bosses.get_mut(&uid) else {
+        return;
+    };
+    info!(
+        "{} (UID {uid}) left view ({reason:?}) at HP {:?}/{:?}",
+        tracked_boss.name,
+        tracked_boss.curr_hp(),
+        tracked_boss.max_hp
+    );
+    tracked_boss.left_view = Some(reason);
+    tracked_boss.pos = entity.pos.or(tracked_boss.pos);
+    // a boss vanishing almost dead was killed, the server doesn't always say so
+    let cause = match reason {
+        LeftViewReason::Dead => Some("it disappeared dead"),
+        LeftViewReason::Despawned | LeftViewReason::Destroyed
+            if tracked_boss.hp_pct().is_some_and(|hp_pct| hp_pct <= LOW_HP_DEATH_PCT) =>
+        {
+            Some("it disappeared at low HP")
+        }
+        _ => None,
+    };
+    if let Some(cause) = cause {
+        let pos = report_position(tracked_boss.pos, &encounter.local_player);
+        report_boss_death(tracked_boss, uid, pos, cause, &mut encounter.pending_reports);
+    }
+}
+
+/// An entity first seen in a delta update. A tracked boss evicted while idle gets back the
+/// attributes of its appearance, deltas only carry the changed ones.
+fn new_entity(entity_type: blueprotobuf::EEntityType, tracked_boss: Option<&TrackedBoss>) -> Entity {
+    Entity {
+        entity_type,
+        monster_id: tracked_boss.map(|tracked_boss| tracked_boss.monster_id),
+        curr_hp: tracked_boss.and_then(TrackedBoss::curr_hp),
+        max_hp: tracked_boss.and_then(|tracked_boss| tracked_boss.max_hp),
+        pos: tracked_boss.and_then(|tracked_boss| tracked_boss.pos),
+        ..Default::default()
+    }
+}
+
+/// Drops entities that weren't updated within `ENTITY_TTL_MS`. Runs at most once every
+/// `ENTITY_EVICTION_INTERVAL_MS`.
+pub fn evict_stale_entities(encounter: &mut Encounter, now_ms: u128) {
+    if now_ms.saturating_sub(encounter.time_last_eviction_ms) < ENTITY_EVICTION_INTERVAL_MS {
+        return;
+    }
+    encounter.time_last_eviction_ms = now_ms;
+    let stale_uids: Vec<i64> = encounter
+        .entity_uid_to_entity
+        .iter()
+        .filter(|(uid, entity)| {
+            Some(**uid) != encounter.local_player_uid
+                && now_ms.saturating_sub(entity.last_seen_ms) > ENTITY_TTL_MS
+        })
+        .map(|(uid, _)| *uid)
+        .collect();
+    for uid in stale_uids {
+        remove_entity(encounter, uid, LeftViewReason::Expired);
+    }
+    encounter.tracked_bosses.retain(|_, tracked_boss| {
+        tracked_boss.in_view()
+            || now_ms.saturating_sub(tracked_boss.last_seen_ms) <= TRACKED_BOSS_TTL_MS
+    });
+}
+
+/// Applies a message sent by the server to the encounter. Fails when the payload doesn't
+/// match the message type.
+pub fn process_notify(encounter: &mut Encounter, op: Pkt, data: Bytes) -> Result<(), prost::DecodeError> {
+    if op == Pkt::ServerChangeInfo {
+        on_server_change(encounter);
+        return Ok(());
+    }
+    // calls and returns are only journaled
+    op.handle_notify(&data, encounter).unwrap_or(Ok(()))
+}
+
+impl NotifyHandler for Encounter {
+    fn on_sync_near_entities(&mut self, sync_near_entities: blueprotobuf::SyncNearEntities) {
+        if process_sync_near_entities(self, sync_near_entities).is_none() {
+            warn!("Error processing SyncNearEntities.. ignoring.");
+        }
+    }
+
+    fn on_sync_container_data(&mut self, sync_container_data: blueprotobuf::SyncContainerData) {
+        if process_sync_container_data(self, sync_container_data).is_none() {
+            warn!("Error processing SyncContainerData.. ignoring.");
+        }
+    }
+
+    fn on_sync_container_dirty_data(&mut self, sync_container_dirty_data: blueprotobuf::SyncContainerDirtyData) {
+        if process_sync_container_dirty_data(self, sync_container_dirty_data).is_none() {
+            warn!("Error processing SyncContainerDirtyData.. ignoring.");
+        }
+    }
+
+    fn on_sync_near_delta_info(&mut self, sync_near_delta_info: blueprotobuf::SyncNearDeltaInfo) {
+        for aoi_sync_delta in sync_near_delta_info.delta_infos {
+            if process_aoi_sync_delta(self, aoi_sync_delta).is_none() {
+                warn!("Error processing SyncNearDeltaInfo.. ignoring.");
+            }
+        }
+    }
+
+    fn on_sync_to_me_delta_info(&mut self, sync_to_me_delta_info: blueprotobuf::SyncToMeDeltaInfo) {
+        if process_sync_to_me_delta_info(self, sync_to_me_delta_info).is_none() {
+            warn!("Error processing SyncToMeDeltaInfo.. ignoring.");
+        }
+    }
+
+    fn on_client_kick_off(&mut self, notify_client_kick_off: blueprotobuf::NotifyClientKickOff) {
+        let reason = notify_client_kick_off
+            .err_code
+            .and_then(|err_code| blueprotobuf::EErrorCode::try_from(err_code).ok());
+        warn!("Kicked off by the server: {reason:?}");
+    }
+}
+
 pub fn process_sync_near_entities(
     encounter: &mut Encounter,
     sync_near_entities: blueprotobuf::SyncNearEntities,
 ) -> Option<()> {
+    for disappear_entity in sync_near_entities.disappear {
+        let Some(target_uuid) = disappear_entity.uuid else {
+            continue;
+        };
+        // without a type it can't be told apart from walking out of range
+        let disappear_type = disappear_entity
+            .r#type
+            .and_then(|disappear_type| blueprotobuf::EDisappearType::try_from(disappear_type).ok())
+            .unwrap_or(blueprotobuf::EDisappearType::EDisappearNormal);
+        remove_entity(encounter, target_uuid >> 16, disappear_type.into());
+    }
+
+    let timestamp_ms = now_ms();
     for pkt_entity in sync_near_entities.appear {
         let target_uuid = pkt_entity.uuid?;
         let target_uid = target_uuid >> 16;
         let target_entity_type = blueprotobuf::EEntityType::from(target_uuid);
+        if !is_tracked_entity_type(target_entity_type) {
+            if target_entity_type == blueprotobuf::EEntityType::EntErrType {
+                debug!("Ignoring entity with unknown type, uuid {target_uuid:#x}");
+            }
+            continue;
+        }
 
         let target_entity = encounter
             .entity_uid_to_entity
             .entry(target_uid)
             .or_default();
         target_entity.entity_type = target_entity_type;
+        target_entity.last_seen_ms = timestamp_ms;
 
         match target_entity_type {
-            blueprotobuf::EEntityType::EntChar => process_player_attrs(target_entity, target_uid, pkt_entity.attrs?.attrs),
-            blueprotobuf::EEntityType::EntMonster => process_monster_attrs(target_entity, pkt_entity.attrs?.attrs, encounter.local_player.as_ref()),
+            blueprotobuf::EEntityType::EntChar => {
+                process_player_attrs(target_entity, target_uid, pkt_entity.attrs?.attrs);
+            }
+            blueprotobuf::EEntityType::EntMonster => process_monster_attrs(target_entity, target_uid, pkt_entity.attrs?.attrs, &encounter.local_player, &mut encounter.tracked_bosses, &mut encounter.pending_reports),
             _ => {}
         }
     }
@@ -45,16 +205,44 @@ pub fn process_sync_container_data(
     sync_container_data: blueprotobuf::SyncContainerData,
 ) -> Option<()> {
     let v_data = sync_container_data.v_data?;
-    let player_uid = v_data.char_id?;
+    apply_local_player_data(encounter, v_data.char_id, &v_data);
+    Some(())
+}
+
+pub fn process_sync_container_dirty_data(
+    encounter: &mut Encounter,
+    sync_container_dirty_data: blueprotobuf::SyncContainerDirtyData,
+) -> Option<()> {
+    let buffer = sync_container_dirty_data.v_data?.buffer?;
+    let char_serialize = decode_char_serialize(&buffer)
+        .inspect_err(|e| warn!("Failed to decode dirty data: {e}"))
+        .ok()?;
+    apply_local_player_data(encounter, encounter.local_player_uid, &char_serialize);
+    Some(())
+}
 
+/// Applies the fields set in `char_serialize`, from a full or a dirty sync, to the local player.
+fn apply_local_player_data(
+    encounter: &mut Encounter,
+    player_uid: Option<i64>,
+    char_serialize: &blueprotobuf::CharSerialize,
+) {
+    if let Some(scene_data) = &char_serialize.scene_data {
+        encounter.local_player.apply_scene_data(scene_data);
+    }
+    let Some(char_base) = &char_serialize.char_base else {
+        return;
+    };
+    let (Some(player_uid), Some(name)) = (player_uid, &char_base.name) else {
+        return;
+    };
     let target_entity = encounter
         .entity_uid_to_entity
         .entry(player_uid)
         .or_default();
-    let char_base = v_data.char_base?;
-    target_entity.name = Some(char_base.name?);
+    target_entity.last_seen_ms = now_ms();
+    target_entity.name = Some(name.clone());
     target_entity.entity_type = blueprotobuf::EEntityType::EntChar;
-    Some(())
 }
 
 pub fn process_sync_to_me_delta_info(
@@ -62,8 +250,10 @@ pub fn process_sync_to_me_delta_info(
     sync_to_me_delta_info: blueprotobuf::SyncToMeDeltaInfo,
 ) -> Option<()> {
     let delta_info = sync_to_me_delta_info.delta_info?;
-    encounter.local_player_uid = Some(delta_info.uuid? >> 16);
-    process_aoi_sync_delta(encounter, delta_info.base_delta?);
+    let local_player_uid = delta_info.uuid? >> 16;
+    encounter.local_player_uid = Some(local_player_uid);
+    let base_delta = delta_info.base_delta?;
+    process_aoi_sync_delta(encounter, base_delta);
     Some(())
 }
 
@@ -73,63 +263,74 @@ pub fn process_aoi_sync_delta(
 ) -> Option<()> {
     let target_uuid = aoi_sync_delta.uuid?;
     let target_uid = target_uuid >> 16;
+    // where the last hit landed on the target, the closest there is to its own position
+    let damage_pos = aoi_sync_delta
+        .skill_effects
+        .iter()
+        .flat_map(|skill_effect| &skill_effect.damages)
+        .rev()
+        .find_map(|damage| damage.damage_pos)
+        .map(EntityPosition::from);
 
     let target_entity_type = blueprotobuf::EEntityType::from(target_uuid);
-    {
+    if is_tracked_entity_type(target_entity_type) {
         let target_entity = encounter
             .entity_uid_to_entity
             .entry(target_uid)
-            .or_insert_with(|| Entity {
-                entity_type: target_entity_type,
-                ..Default::default()
+            .or_insert_with(|| {
+                new_entity(target_entity_type, encounter.tracked_bosses.get(&target_uid))
             });
+        target_entity.last_seen_ms = now_ms();
+        target_entity.pos = damage_pos.or(target_entity.pos);
 
+        let mut attr_pos = None;
         if let Some(attrs_collection) = aoi_sync_delta.attrs {
             match target_entity_type {
-                blueprotobuf::EEntityType::EntChar => process_player_attrs(target_entity, target_uid, attrs_collection.attrs),
-                blueprotobuf::EEntityType::EntMonster => process_monster_attrs(target_entity, attrs_collection.attrs, encounter.local_player.as_ref()),
+                blueprotobuf::EEntityType::EntChar => attr_pos = process_player_attrs(target_entity, target_uid, attrs_collection.attrs),
+                blueprotobuf::EEntityType::EntMonster => process_monster_attrs(target_entity, target_uid, attrs_collection.attrs, &encounter.local_player, &mut encounter.tracked_bosses, &mut encounter.pending_reports),
                 _ => {}
             }
         }
+        // the local player's position moves with its own deltas between container syncs
+        if let Some(pos) = attr_pos
+            .or(damage_pos)
+            .filter(|_| encounter.local_player_uid == Some(target_uid))
+        {
+            encounter.local_player.pos = Some(pos);
+        }
     }
 
     let Some(skill_effect) = aoi_sync_delta.skill_effects else {
         return Some(()); 
     };
 
-    for _ in skill_effect.damages {
-        let target_entity = encounter.entity_uid_to_entity.get(&target_uid);
-        let monster_id = target_entity.and_then(|e| e.monster_id);
-        let is_crowdsource = monster_id
-            .is_some_and(|id| MONSTER_NAMES_CROWDSOURCE.contains_key(&id));
-        let crowdsource_name = if is_crowdsource {
-        monster_id
-                .and_then(|id| MONSTER_NAMES.get(&id))
-                .or_else(|| target_entity.and_then(|e| e.name.as_ref()))
-                .cloned()
-        } else {
-            None
-        };
-        let crowdsource_remote_id = monster_id
-            .and_then(|id| MONSTER_UID_CROWDSOURCE_MAP.get(&id).cloned());
-
-        if is_crowdsource {
+    let is_killing_blow = skill_effect.damages.iter().any(|damage| damage.is_dead == Some(true));
+    if let Some(tracked_boss) = encounter
+        .tracked_bosses
+        .get_mut(&target_uid)
+        .filter(|_| is_killing_blow)
+    {
+        tracked_boss.pos = damage_pos.or(tracked_boss.pos);
+        let pos = report_position(tracked_boss.pos, &encounter.local_player);
+        report_boss_death(tracked_boss, target_uid, pos, "of a killing blow", &mut encounter.pending_reports);
+    }
 
-            if crowdsource_remote_id.is_none() {
-                warn!(
-                    "live::opcodes_process::handle_damage_packet - crowdsourced monster missing remote id for monster_id={monster_id:?}, monster_name={crowdsource_name:?}"
-                );
-            }
-            encounter.crowdsource_monster_name = crowdsource_name.clone();
-            encounter.crowdsource_monster_id = monster_id;
-            encounter.crowdsource_monster_remote_id = crowdsource_remote_id.clone();
+    let timestamp_ms = now_ms();
+    // the overlay follows the boss being fought
+    if let Some(tracked_boss) = encounter
+        .tracked_bosses
+        .get_mut(&target_uid)
+        .filter(|_| !skill_effect.damages.is_empty())
+    {
+        if tracked_boss.remote_id.is_none() && encounter.last_damaged_uid != Some(target_uid) {
+            warn!(
+                "live::opcodes_process::process_aoi_sync_delta - crowdsourced monster missing remote id for monster_id={}, monster_name={}",
+                tracked_boss.monster_id, tracked_boss.name
+            );
         }
+        encounter.last_damaged_uid = Some(target_uid);
+        encounter.pinned_monster_id = None;
     }
-
-    let timestamp_ms = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis();
     if encounter.time_fight_start_ms == Default::default() {
         encounter.time_fight_start_ms = timestamp_ms;
     }
@@ -138,108 +339,602 @@ pub fn process_aoi_sync_delta(
 }
 
 
-fn process_player_attrs(player_entity: &mut Entity, player_uid: i64, attrs: Vec<blueprotobuf::Attr>) {
-    for attr in attrs {
-        let Some(mut raw_bytes) = attr.raw_data else {
-            continue;
-        };
-        let Some(attr_id) = attr.id else { continue; };
-
-        match attr_id {
-            attr_type::ATTR_NAME => {
-                raw_bytes.remove(0);
-                let player_name_result = BinaryReader::from(raw_bytes).read_string();
-                if let Ok(player_name) = player_name_result {
-                    player_entity.name = Some(player_name.clone());
-                    info!("Found player {player_name} with UID {player_uid}");
-                } else {
-                    warn!("Failed to read player name for UID {player_uid}");
-                }
+/// Decodes the attributes with a known id, malformed ones are logged and skipped.
+fn decode_attrs(attrs: &[blueprotobuf::Attr], entity_uid: i64) -> impl Iterator<Item = (i32, AttrValue)> + '_ {
+    attrs.iter().filter_map(move |attr| {
+        let attr_id = attr.id?;
+        match decode_attr(attr_id, attr.raw_data.as_deref()?) {
+            Ok(value) => Some((attr_id, value)),
+            Err(AttrError::Unknown(_)) => None,
+            Err(e) => {
+                warn!("Skipping {e} of entity UID {entity_uid}");
+                None
+            }
+        }
+    })
+}
+
+/// Returns the position carried by the attributes, if any.
+fn process_player_attrs(player_entity: &mut Entity, player_uid: i64, attrs: Vec<blueprotobuf::Attr>) -> Option<EntityPosition> {
+    let mut pos = None;
+    for (attr_id, value) in decode_attrs(&attrs, player_uid) {
+        match (attr_id, value) {
+            (attr_type::ATTR_NAME, AttrValue::String(player_name)) => {
+                info!("Found player {player_name} with UID {player_uid}");
+                player_entity.name = Some(player_name);
             }
+            (_, AttrValue::Position { x, y, z }) => pos = Some(EntityPosition { x, y, z }),
             _ => (),
         }
     }
+    player_entity.pos = pos.or(player_entity.pos);
+    pos
+}
+
+/// Position sent in HP reports, the boss's own when known, otherwise where the local player
+/// stands.
+pub fn report_position(boss_pos: Option<EntityPosition>, local_player: &LocalPlayerState) -> Option<(f32, f32)> {
+    let pos = boss_pos.or(local_player.pos)?;
+    Some((pos.x, pos.y))
 }
 
 fn process_monster_attrs(
     monster_entity: &mut Entity,
+    monster_uid: i64,
     attrs: Vec<blueprotobuf::Attr>,
-    local_player: Option<&blueprotobuf::SyncContainerData>,
+    local_player: &LocalPlayerState,
+    tracked_bosses: &mut HashMap<i64, TrackedBoss>,
+    pending_reports: &mut Vec<HpReport>,
 ) {
-    for attr in attrs {
-        let Some(raw_bytes) = attr.raw_data else { continue; };
-        let Some(attr_id) = attr.id else { continue; };
-
-        #[allow(clippy::cast_possible_truncation)]
-        match attr_id {
-            attr_type::ATTR_ID => monster_entity.monster_id = Some(prost::encoding::decode_varint(&mut raw_bytes.as_slice()).unwrap() as i32),
-            attr_type::ATTR_HP => {
-                let curr_hp = prost::encoding::decode_varint(&mut raw_bytes.as_slice()).unwrap() as i32;
-                let prev_hp_opt = monster_entity.curr_hp;
-                monster_entity.curr_hp = Some(curr_hp);
-
-                let endpoint = format!("{BPTIMER_BASE_URL}{CREATE_HP_REPORT_ENDPOINT}");
-                let (Some(monster_id), Some(local_player)) = (monster_entity.monster_id, &local_player) else {
-                    continue;
-                };
-                let Some(max_hp) = monster_entity.max_hp else {
-                    continue;
-                };
-                if MONSTER_NAMES_CROWDSOURCE.contains_key(&monster_id) { // only record if it's a world boss, magical creature, etc.
-                    let monster_name = MONSTER_NAMES.get(&monster_id).map_or("Unknown Monster Name", |s| s.as_str());
-                    let new_hp_pct = (curr_hp * 100 / max_hp).clamp(0, 100);
-                    let old_hp_pct_opt =
-                        prev_hp_opt.map(|prev_hp| (prev_hp * 100 / max_hp).clamp(0, 100));
-                    let Some((Some(line), Some(pos_x), Some(pos_y))) = local_player.v_data.as_ref()
-                                                                               .and_then(|v| v.scene_data.as_ref())
-                                                                               .map(|s| (
-                                                                                   s.line_id,
-                                                                                   s.pos.as_ref().and_then(|p| p.x),
-                                                                                   s.pos.as_ref().and_then(|p| p.y),
-                                                                               ))
-                    else {
-                        continue;
-                    };
-
-                    let should_report = match old_hp_pct_opt {
-                        None => true,
-                        Some(old_hp_pct) => old_hp_pct != new_hp_pct && new_hp_pct % 5 == 0,
-                    };
-
-                    if should_report {
-                        info!("Found crowdsourced monster with Name {monster_name} - ID {monster_id} - HP% {new_hp_pct}% on line {line} and pos ({pos_x},{pos_y})");
-                        let body = serde_json::json!({
-                            "monster_id": monster_id,
-                            "hp_pct": new_hp_pct,
-                            "line": line,
-                            "pos_x": pos_x,
-                            "pos_y": pos_y,
-                        });
-                        tokio::spawn(async move {
-                            let endpoint = endpoint.clone();
-                            let client = reqwest::Client::new();
-                            let res = client
-                                .post(endpoint)
-                                .header("X-API-Key", CROWD_SOURCE_API_KEY)
-                                .json(&body)
-                                .send().await;
-                            match res {
-                                Ok(resp) => {
-                                    if resp.status() != reqwest::StatusCode::OK {
-                                        error!("POST monster info failed: status {}", resp.status());
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Failed to POST monster info: {e}");
-                                }
-                            }
-                        });
-                    }
-                }
-            }
-            #[allow(clippy::cast_possible_truncation)]
-            attr_type::ATTR_MAX_HP => monster_entity.max_hp = Some(prost::encoding::decode_varint(&mut raw_bytes.as_slice()).unwrap() as i32),
+    let mut curr_hp = None;
+    for (attr_id, value) in decode_attrs(&attrs, monster_uid) {
+        match (attr_id, value) {
+            (attr_type::ATTR_ID, value) => monster_entity.monster_id = value.as_i32(),
+            (attr_type::ATTR_HP, AttrValue::Hp(hp)) => curr_hp = Some(hp),
+            (attr_type::ATTR_MAX_HP, AttrValue::Hp(max_hp)) => monster_entity.max_hp = Some(max_hp),
+            (_, AttrValue::Position { x, y, z }) => monster_entity.pos = Some(EntityPosition { x, y, z }),
             _ => (),
         }
     }
+
+    // only world bosses, magical creatures, etc. are tracked and reported
+    let Some(monster_id) = monster_entity.monster_id.filter(|_| monster_entity.is_crowdsourced()) else {
+        monster_entity.curr_hp = curr_hp.or(monster_entity.curr_hp);
+        return;
+    };
+    let timestamp_ms = now_ms();
+    let tracked_boss = tracked_bosses
+        .entry(monster_uid)
+        .or_insert_with(|| TrackedBoss::new(monster_id));
+    tracked_boss.left_view = None;
+    tracked_boss.last_seen_ms = timestamp_ms;
+    tracked_boss.line_id = local_player.line_id.or(tracked_boss.line_id);
+    tracked_boss.max_hp = monster_entity.max_hp.or(tracked_boss.max_hp);
+    tracked_boss.pos = monster_entity.pos.or(tracked_boss.pos);
+
+    // HP is handled last so the report sees the id and max HP of the same update
+    let Some(curr_hp) = curr_hp else {
+        return;
+    };
+    monster_entity.curr_hp = Some(curr_hp);
+    tracked_boss.record_hp(curr_hp, timestamp_ms);
+    let pos = report_position(tracked_boss.pos, local_player);
+    if curr_hp <= 0 {
+        report_boss_death(tracked_boss, monster_uid, pos, "its HP reached 0", pending_reports);
+        return;
+    }
+    if tracked_boss.death_reported || tracked_boss.death_report_in_flight {
+        return;
+    }
+    let (Some(line), Some((pos_x, pos_y))) = (tracked_boss.line_id, pos) else {
+        return;
+    };
+    let Some(hp_pct) = tracked_boss.take_hp_report() else {
+        return;
+    };
+    info!("Found crowdsourced monster with Name {} - ID {monster_id} - HP% {hp_pct}% on line {line} and pos ({pos_x},{pos_y})", tracked_boss.name);
+    pending_reports.push(HpReport {
+        uid: Some(monster_uid),
+        monster_id,
+        hp_pct,
+        line,
+        pos_x,
+        pos_y,
+    });
+}
+
+/// Queues the 0% report of a boss, unless it was already sent or is being sent.
+fn report_boss_death(
+    tracked_boss: &mut TrackedBoss,
+    uid: i64,
+    pos: Option<(f32, f32)>,
+    cause: &str,
+    pending_reports: &mut Vec<HpReport>,
+) {
+    if !tracked_boss.start_death_report() {
+        return;
+    }
+    let (Some(line), Some((pos_x, pos_y))) = (tracked_boss.line_id, pos) else {
+        tracked_boss.finish_death_report(false);
+        warn!("{} (UID {uid}) died because {cause} but its line or position is unknown, not reported", tracked_boss.name);
+        return;
+    };
+    tracked_boss.last_reported_hp_pct = Some(0);
+    info!("{} (UID {uid}) died on line {line} because {cause}, reporting 0%", tracked_boss.name);
+    pending_reports.push(HpReport {
+        uid: Some(uid),
+        monster_id: tracked_boss.monster_id,
+        hp_pct: 0,
+        line,
+        pos_x,
+        pos_y,
+    });
+}
+
+/// Posts an HP report to bptimer.
+pub async fn send_hp_report(report: &HpReport) -> Result<(), String> {
+    if HP_REPORT_DRY_RUN.load(Ordering::Relaxed) {
+        info!("Dry run, not sending {report:?}");
+        return Ok(());
+    }
+    let body = serde_json::json!({
+        "monster_id": report.monster_id,
+        "hp_pct": report.hp_pct,
+        "line": report.line,
+        "pos_x": report.pos_x,
+        "pos_y": report.pos_y,
+    });
+    let response = reqwest::Client::new()
+        .post(format!("{BPTIMER_BASE_URL}{CREATE_HP_REPORT_ENDPOINT}"))
+        .header("X-API-Key", CROWD_SOURCE_API_KEY)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|err| format!("Failed to send HP report: {err}"))?;
+    if !response.status().is_success() {
+        return Err(format!("HP report failed with status {}", response.status()));
+    }
+    Ok(())
+}
+
+/// Records the outcome of a report, a boss only counts as reported dead once its 0% report
+/// was sent.
+pub fn on_hp_report_sent(encounter: &mut Encounter, report: &HpReport, sent: bool) {
+    if report.hp_pct != 0 {
+        return;
+    }
+    if let Some(tracked_boss) = report.uid.and_then(|uid| encounter.tracked_bosses.get_mut(&uid)) {
+        tracked_boss.finish_death_report(sent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::opcodes_models::{
+        attr_type, Encounter, EntityPosition, HpReport, LeftViewReason, ENTITY_EVICTION_INTERVAL_MS,
+    };
+    use crate::live::opcodes_process::{
+        evict_stale_entities, now_ms, on_hp_report_sent, process_aoi_sync_delta,
+        process_notify, process_sync_container_data, process_sync_near_entities,
+        process_sync_to_me_delta_info, send_hp_report, HP_REPORT_DRY_RUN,
+    };
+    use crate::packets::opcodes::Pkt;
+    use blueprotobuf_lib::blueprotobuf;
+    use bytes::Bytes;
+    use prost::Message;
+    use std::sync::atomic::Ordering;
+
+    // a crowdsourced world boss
+    const BOSS_ID: i32 = 10032;
+    const OTHER_BOSS_ID: i32 = 10009;
+
+    fn attr(id: i32, value: u64) -> blueprotobuf::Attr {
+        let mut raw_data = Vec::new();
+        prost::encoding::encode_varint(value, &mut raw_data);
+        blueprotobuf::Attr {
+            id: Some(id),
+            raw_data: Some(raw_data),
+        }
+    }
+
+    fn monster_uuid(uid: i64) -> i64 {
+        (uid << 16) | ((blueprotobuf::EEntityType::EntMonster as i64) << 6)
+    }
+
+    fn boss_appear(uid: i64, hp: u64) -> blueprotobuf::SyncNearEntities {
+        monster_appear(uid, BOSS_ID, hp)
+    }
+
+    fn monster_appear(uid: i64, monster_id: i32, hp: u64) -> blueprotobuf::SyncNearEntities {
+        blueprotobuf::SyncNearEntities {
+            appear: vec![blueprotobuf::Entity {
+                uuid: Some(monster_uuid(uid)),
+                attrs: Some(blueprotobuf::AttrCollection {
+                    attrs: vec![
+                        attr(attr_type::ATTR_ID, monster_id as u64),
+                        attr(attr_type::ATTR_MAX_HP, 1000),
+                        attr(attr_type::ATTR_HP, hp),
+                    ],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            disappear: vec![],
+        }
+    }
+
+    fn disappear(uid: i64, disappear_type: blueprotobuf::EDisappearType) -> blueprotobuf::SyncNearEntities {
+        blueprotobuf::SyncNearEntities {
+            appear: vec![],
+            disappear: vec![blueprotobuf::DisappearEntity {
+                uuid: Some(monster_uuid(uid)),
+                r#type: Some(disappear_type as i32),
+            }],
+        }
+    }
+
+    fn hp_delta(uid: i64, hp: u64) -> blueprotobuf::AoiSyncDelta {
+        blueprotobuf::AoiSyncDelta {
+            uuid: Some(monster_uuid(uid)),
+            attrs: Some(blueprotobuf::AttrCollection {
+                attrs: vec![attr(attr_type::ATTR_HP, hp)],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn damage(uid: i64) -> blueprotobuf::AoiSyncDelta {
+        blueprotobuf::AoiSyncDelta {
+            uuid: Some(monster_uuid(uid)),
+            skill_effects: Some(blueprotobuf::SkillEffect {
+                damages: vec![blueprotobuf::SyncDamageInfo {
+                    value: Some(100),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// A hit landing on the boss at `(x, y)`, with its HP after the hit.
+    fn hit_at(uid: i64, x: f32, y: f32, hp: u64) -> blueprotobuf::AoiSyncDelta {
+        let mut delta = damage(uid);
+        delta.skill_effects.as_mut().unwrap().damages[0].damage_pos = Some(blueprotobuf::Vector3 {
+            x: Some(x),
+            y: Some(y),
+            z: Some(0.0),
+        });
+        delta.attrs = hp_delta(uid, hp).attrs;
+        delta
+    }
+
+    fn killing_blow(uid: i64) -> blueprotobuf::AoiSyncDelta {
+        let mut delta = damage(uid);
+        delta.skill_effects.as_mut().unwrap().damages[0].is_dead = Some(true);
+        delta
+    }
+
+    /// An encounter where the local player's line and position are known, so reports are queued.
+    fn reporting_encounter() -> Encounter {
+        let mut encounter = Encounter::default();
+        encounter.local_player.line_id = Some(3);
+        encounter.local_player.pos = Some(EntityPosition { x: 10.0, y: 20.0, z: 0.0 });
+        encounter
+    }
+
+    /// Takes the queued reports as `(uid, hp_pct)`.
+    fn take_reports(encounter: &mut Encounter) -> Vec<(i64, i64)> {
+        std::mem::take(&mut encounter.pending_reports)
+            .into_iter()
+            .map(|report| (report.uid.unwrap(), report.hp_pct))
+            .collect()
+    }
+
+    #[test]
+    fn test_hp_0_reports_death_until_sent() {
+        let mut encounter = reporting_encounter();
+        process_sync_near_entities(&mut encounter, boss_appear(1, 900));
+        assert_eq!(take_reports(&mut encounter), vec![(1, 90)]);
+
+        process_aoi_sync_delta(&mut encounter, hp_delta(1, 0));
+        let report = encounter.pending_reports[0].clone();
+        assert_eq!(take_reports(&mut encounter), vec![(1, 0)]);
+        assert!(!encounter.tracked_bosses[&1].death_reported);
+        // not again while it is being sent
+        process_aoi_sync_delta(&mut encounter, hp_delta(1, 0));
+        assert_eq!(take_reports(&mut encounter), vec![]);
+
+        // a failed report is sent again on the next death signal
+        on_hp_report_sent(&mut encounter, &report, false);
+        assert!(!encounter.tracked_bosses[&1].death_reported);
+        process_aoi_sync_delta(&mut encounter, hp_delta(1, 0));
+        assert_eq!(take_reports(&mut encounter), vec![(1, 0)]);
+        on_hp_report_sent(&mut encounter, &report, true);
+        assert!(encounter.tracked_bosses[&1].death_reported);
+
+        // other death signals don't repeat it
+        process_aoi_sync_delta(&mut encounter, killing_blow(1));
+        process_sync_near_entities(&mut encounter, disappear(1, blueprotobuf::EDisappearType::EDisappearDead));
+        assert_eq!(take_reports(&mut encounter), vec![]);
+    }
+
+    #[test]
+    fn test_killing_blow_reports_death() {
+        let mut encounter = reporting_encounter();
+        process_sync_near_entities(&mut encounter, boss_appear(1, 900));
+        take_reports(&mut encounter);
+        process_aoi_sync_delta(&mut encounter, damage(1));
+        assert_eq!(take_reports(&mut encounter), vec![]);
+        process_aoi_sync_delta(&mut encounter, killing_blow(1));
+        assert_eq!(take_reports(&mut encounter), vec![(1, 0)]);
+        assert!(encounter.tracked_bosses[&1].death_report_in_flight);
+    }
+
+    #[test]
+    fn test_dead_or_low_hp_disappear_reports_death() {
+        let mut encounter = reporting_encounter();
+        for uid in 1..=5 {
+            // 2%
+            process_sync_near_entities(&mut encounter, boss_appear(uid, 20));
+        }
+        // 3%
+        process_sync_near_entities(&mut encounter, boss_appear(6, 30));
+        process_sync_near_entities(&mut encounter, boss_appear(7, 500));
+        take_reports(&mut encounter);
+
+        process_sync_near_entities(&mut encounter, disappear(1, blueprotobuf::EDisappearType::EDisappearNormal));
+        process_sync_near_entities(&mut encounter, disappear(2, blueprotobuf::EDisappearType::EDisappearDestroy));
+        process_sync_near_entities(
+            &mut encounter,
+            blueprotobuf::SyncNearEntities {
+                appear: vec![],
+                disappear: vec![blueprotobuf::DisappearEntity {
+                    uuid: Some(monster_uuid(3)),
+                    r#type: None,
+                }],
+            },
+        );
+        assert_eq!(take_reports(&mut encounter), vec![(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(encounter.tracked_bosses[&3].left_view, Some(LeftViewReason::Despawned));
+
+        // a boss changing line or left behind isn't dead, nor one that still had HP left
+        process_sync_near_entities(
+            &mut encounter,
+            disappear(4, blueprotobuf::EDisappearType::EDisappearTransferPassLineLeave),
+        );
+        encounter.entity_uid_to_entity.get_mut(&5).unwrap().last_seen_ms = 0;
+        evict_stale_entities(&mut encounter, now_ms());
+        process_sync_near_entities(&mut encounter, disappear(6, blueprotobuf::EDisappearType::EDisappearNormal));
+        process_sync_near_entities(&mut encounter, disappear(7, blueprotobuf::EDisappearType::EDisappearDestroy));
+        assert_eq!(take_reports(&mut encounter), vec![]);
+        assert_eq!(encounter.tracked_bosses[&5].left_view, Some(LeftViewReason::Expired));
+
+        // the server's word counts at any HP
+        process_sync_near_entities(&mut encounter, boss_appear(8, 500));
+        take_reports(&mut encounter);
+        process_sync_near_entities(&mut encounter, disappear(8, blueprotobuf::EDisappearType::EDisappearDead));
+        assert_eq!(take_reports(&mut encounter), vec![(8, 0)]);
+        assert_eq!(encounter.tracked_bosses[&8].left_view, Some(LeftViewReason::Dead));
+    }
+
+    #[test]
+    fn test_notify_messages_go_to_their_handler() {
+        let mut encounter = reporting_encounter();
+        let appear = Bytes::from(boss_appear(1, 900).encode_to_vec());
+        process_notify(&mut encounter, Pkt::SyncNearEntities, appear).unwrap();
+        assert_eq!(take_reports(&mut encounter), vec![(1, 90)]);
+        let deltas = blueprotobuf::SyncNearDeltaInfo {
+            delta_infos: vec![hp_delta(1, 500), killing_blow(1)],
+        };
+        let deltas = Bytes::from(deltas.encode_to_vec());
+        process_notify(&mut encounter, Pkt::SyncNearDeltaInfo, deltas).unwrap();
+        assert_eq!(take_reports(&mut encounter), vec![(1, 50), (1, 0)]);
+
+        // messages without a handler are only decoded
+        let kick_off = blueprotobuf::NotifyClientKickOff { err_code: Some(1) };
+        let kick_off = Bytes::from(kick_off.encode_to_vec());
+        process_notify(&mut encounter, Pkt::NotifyClientKickOff, kick_off).unwrap();
+        process_notify(&mut encounter, Pkt::SyncSceneEvents, Bytes::new()).unwrap();
+        let truncated = Bytes::from_static(&[0x08, 0x80]);
+        assert!(process_notify(&mut encounter, Pkt::SyncServerTime, truncated).is_err());
+        assert!(encounter.tracked_bosses.contains_key(&1));
+
+        process_notify(&mut encounter, Pkt::ServerChangeInfo, Bytes::new()).unwrap();
+        assert!(encounter.tracked_bosses.is_empty());
+    }
+
+    #[test]
+    fn test_two_bosses_in_view_are_tracked_separately() {
+        let mut encounter = Encounter::default();
+        encounter.local_player.line_id = Some(3);
+        process_sync_near_entities(&mut encounter, boss_appear(1, 900));
+        process_sync_near_entities(&mut encounter, monster_appear(2, OTHER_BOSS_ID, 800));
+        assert_eq!(encounter.current_monster_id(), None);
+
+        process_aoi_sync_delta(&mut encounter, hp_delta(1, 500));
+        process_aoi_sync_delta(&mut encounter, damage(1));
+        assert_eq!(encounter.current_monster_id(), Some(BOSS_ID));
+        // fighting the other boss doesn't touch the first one
+        process_aoi_sync_delta(&mut encounter, hp_delta(2, 600));
+        process_aoi_sync_delta(&mut encounter, damage(2));
+        assert_eq!(encounter.current_monster_id(), Some(OTHER_BOSS_ID));
+
+        let (first, second) = (&encounter.tracked_bosses[&1], &encounter.tracked_bosses[&2]);
+        assert_eq!((first.monster_id, first.curr_hp(), first.hp_pct()), (BOSS_ID, Some(500), Some(50)));
+        assert_eq!((second.monster_id, second.curr_hp(), second.hp_pct()), (OTHER_BOSS_ID, Some(600), Some(60)));
+        assert_eq!((first.hp_history.len(), second.hp_history.len()), (2, 2));
+        assert!(first.in_view() && second.in_view());
+        assert_eq!(encounter.tracked_boss_uid(BOSS_ID, 3), Some(1));
+        assert_eq!(encounter.tracked_boss_uid(OTHER_BOSS_ID, 3), Some(2));
+
+        // one leaving view keeps the other in view
+        process_sync_near_entities(&mut encounter, disappear(2, blueprotobuf::EDisappearType::EDisappearNormal));
+        assert!(encounter.tracked_bosses[&1].in_view());
+        assert!(!encounter.tracked_bosses[&2].in_view());
+    }
+
+    #[test]
+    fn test_picked_monster_is_followed_until_a_boss_is_damaged() {
+        let mut encounter = Encounter {
+            pinned_monster_id: Some(OTHER_BOSS_ID),
+            ..Default::default()
+        };
+        process_sync_near_entities(&mut encounter, boss_appear(1, 900));
+        process_sync_near_entities(&mut encounter, monster_appear(2, OTHER_BOSS_ID, 800));
+        assert_eq!(encounter.current_monster_id(), Some(OTHER_BOSS_ID));
+
+        process_aoi_sync_delta(&mut encounter, damage(1));
+        assert_eq!(encounter.pinned_monster_id, None);
+        assert_eq!(encounter.current_monster_id(), Some(BOSS_ID));
+    }
+
+    #[test]
+    fn test_reports_use_the_boss_position() {
+        let take_positions = |encounter: &mut Encounter| -> Vec<(f32, f32)> {
+            std::mem::take(&mut encounter.pending_reports)
+                .into_iter()
+                .map(|report| (report.pos_x, report.pos_y))
+                .collect()
+        };
+        let mut encounter = Encounter::default();
+        let scene_data = blueprotobuf::SceneData {
+            line_id: Some(3),
+            pos: Some(blueprotobuf::Position {
+                x: Some(10.0),
+                y: Some(20.0),
+                z: Some(1.0),
+                dir: None,
+            }),
+            ..Default::default()
+        };
+        process_sync_container_data(
+            &mut encounter,
+            blueprotobuf::SyncContainerData {
+                v_data: Some(blueprotobuf::CharSerialize {
+                    scene_data: Some(scene_data),
+                    ..Default::default()
+                }),
+            },
+        );
+
+        // until the boss is hit its position is unknown, the local player's is sent
+        process_sync_near_entities(&mut encounter, boss_appear(1, 900));
+        assert_eq!(take_positions(&mut encounter), vec![(10.0, 20.0)]);
+        process_aoi_sync_delta(&mut encounter, hit_at(1, 50.0, 60.0, 800));
+        assert_eq!(take_positions(&mut encounter), vec![(50.0, 60.0)]);
+        assert_eq!(encounter.entity_uid_to_entity[&1].pos, Some(EntityPosition { x: 50.0, y: 60.0, z: 0.0 }));
+
+        // the boss's last position is kept once it's out of view
+        process_sync_near_entities(&mut encounter, disappear(1, blueprotobuf::EDisappearType::EDisappearDead));
+        assert_eq!(take_positions(&mut encounter), vec![(50.0, 60.0)]);
+    }
+
+    #[test]
+    fn test_local_player_position_follows_its_deltas() {
+        let player_uuid = |uid: i64| (uid << 16) | ((blueprotobuf::EEntityType::EntChar as i64) << 6);
+        let hit_at = |uuid: i64, x: f32, y: f32| blueprotobuf::AoiSyncDelta {
+            uuid: Some(uuid),
+            skill_effects: Some(blueprotobuf::SkillEffect {
+                damages: vec![blueprotobuf::SyncDamageInfo {
+                    damage_pos: Some(blueprotobuf::Vector3 {
+                        x: Some(x),
+                        y: Some(y),
+                        z: Some(1.0),
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut encounter = reporting_encounter();
+        process_sync_to_me_delta_info(
+            &mut encounter,
+            blueprotobuf::SyncToMeDeltaInfo {
+                delta_info: Some(blueprotobuf::AoiSyncToMeDelta {
+                    uuid: Some(player_uuid(5)),
+                    base_delta: Some(hit_at(player_uuid(5), 30.0, 40.0)),
+                    ..Default::default()
+                }),
+            },
+        );
+        assert_eq!(encounter.local_player_uid, Some(5));
+        assert_eq!(encounter.local_player.pos, Some(EntityPosition { x: 30.0, y: 40.0, z: 1.0 }));
+
+        // another player being hit doesn't move the local player
+        process_aoi_sync_delta(&mut encounter, hit_at(player_uuid(6), 50.0, 60.0));
+        assert_eq!(encounter.local_player.pos, Some(EntityPosition { x: 30.0, y: 40.0, z: 1.0 }));
+        // the local player's deltas come in SyncNearDeltaInfo too
+        process_aoi_sync_delta(&mut encounter, hit_at(player_uuid(5), 35.0, 45.0));
+        assert_eq!(encounter.local_player.pos, Some(EntityPosition { x: 35.0, y: 45.0, z: 1.0 }));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_reports_are_not_sent() {
+        HP_REPORT_DRY_RUN.store(true, Ordering::Relaxed);
+        let report = HpReport {
+            uid: Some(1),
+            monster_id: BOSS_ID,
+            hp_pct: 0,
+            line: 3,
+            pos_x: 10.0,
+            pos_y: 20.0,
+        };
+        let result = send_hp_report(&report).await;
+        HP_REPORT_DRY_RUN.store(false, Ordering::Relaxed);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_disappear_keeps_the_tracked_boss() {
+        let mut encounter = Encounter::default();
+        process_sync_near_entities(&mut encounter, boss_appear(1, 900));
+        process_sync_near_entities(&mut encounter, boss_appear(2, 800));
+        assert!(encounter.tracked_bosses[&1].in_view());
+
+        process_sync_near_entities(&mut encounter, disappear(1, blueprotobuf::EDisappearType::EDisappearNormal));
+        process_sync_near_entities(
+            &mut encounter,
+            disappear(2, blueprotobuf::EDisappearType::EDisappearTransferPassLineLeave),
+        );
+        assert!(encounter.entity_uid_to_entity.is_empty());
+        assert_eq!(encounter.tracked_bosses[&1].left_view, Some(LeftViewReason::Despawned));
+        assert_eq!(encounter.tracked_bosses[&2].left_view, Some(LeftViewReason::Transferred));
+        assert!(!encounter.tracked_bosses[&1].death_reported);
+
+        // back in view
+        process_sync_near_entities(&mut encounter, boss_appear(1, 850));
+        assert!(encounter.tracked_bosses[&1].in_view());
+        assert_eq!(encounter.tracked_bosses[&1].curr_hp(), Some(850));
+    }
+
+    #[test]
+    fn test_stale_entities_are_evicted_and_restored() {
+        let mut encounter = Encounter::default();
+        process_sync_near_entities(&mut encounter, boss_appear(1, 900));
+        encounter.entity_uid_to_entity.get_mut(&1).unwrap().last_seen_ms = 0;
+
+        let now = now_ms();
+        evict_stale_entities(&mut encounter, now);
+        assert!(!encounter.entity_uid_to_entity.contains_key(&1));
+        assert_eq!(encounter.tracked_bosses[&1].left_view, Some(LeftViewReason::Expired));
+
+        // an idle boss that was evicted only sends its changed HP, the rest comes from its appearance
+        process_aoi_sync_delta(&mut encounter, hp_delta(1, 700));
+        let entity = &encounter.entity_uid_to_entity[&1];
+        assert_eq!((entity.monster_id, entity.max_hp, entity.curr_hp), (Some(BOSS_ID), Some(1000), Some(700)));
+        let tracked_boss = &encounter.tracked_bosses[&1];
+        assert!(tracked_boss.in_view());
+        assert_eq!(tracked_boss.hp_pct(), Some(70));
+
+        // bosses out of view are forgotten after their own TTL
+        encounter.entity_uid_to_entity.clear();
+        let tracked_boss = encounter.tracked_bosses.get_mut(&1).unwrap();
+        tracked_boss.left_view = Some(LeftViewReason::Despawned);
+        tracked_boss.last_seen_ms = 0;
+        evict_stale_entities(&mut encounter, now + ENTITY_EVICTION_INTERVAL_MS);
+        assert!(encounter.tracked_bosses.is_empty());
+    }
 }
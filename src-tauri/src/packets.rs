@@ -1,6 +1,15 @@
 // https://doc.rust-lang.org/reference/items/modules.html#module-source-filenames
 // Preferred way is to name modules with their subfolder name now (no longer mod.rs)
+pub mod capture_source;
+pub mod capture_stats;
+pub mod opcode_discovery;
 pub mod opcodes;
 pub mod packet_capture;
 mod packet_process;
+#[cfg(not(windows))]
+mod pcap_source;
+pub mod replay_source;
+pub mod session_recorder;
 pub mod utils;
+#[cfg(windows)]
+mod windivert_source;
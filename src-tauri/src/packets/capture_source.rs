@@ -0,0 +1,204 @@
+use crate::packets::replay_source::{ReplaySource, ReplayTiming};
+use log::warn;
+use std::path::PathBuf;
+
+/// What a `CaptureSource` produced when asked for its next packet.
+pub enum NextPacket<'a> {
+    Packet(&'a [u8]),
+    /// Nothing arrived within the read timeout of the source.
+    Idle,
+    /// The source is closed or failed.
+    Closed,
+}
+
+/// A source of raw network packets for the capture pipeline.
+///
+/// Every packet handed out starts at the IP header, so server detection and
+/// reassembly don't need to know which backend produced it.
+pub trait CaptureSource: Send {
+    /// Name of the backend, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Blocks until the next packet is available. Sources are read on their own thread, see
+    /// `packet_capture::spawn_source_reader`, and should return `NextPacket::Idle` every
+    /// now and then so the thread notices when capture was restarted.
+    fn next_packet(&mut self) -> NextPacket<'_>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureBackend {
+    /// WinDivert in sniff mode (Windows only).
+    WinDivert,
+    /// libpcap on the given device, or the platform default device.
+    Pcap { device: Option<String> },
+    /// A recorded pcap/pcapng file.
+    Replay { path: PathBuf, timing: ReplayTiming },
+}
+
+impl Default for CaptureBackend {
+    fn default() -> Self {
+        if cfg!(windows) {
+            CaptureBackend::WinDivert
+        } else {
+            CaptureBackend::Pcap { device: None }
+        }
+    }
+}
+
+impl CaptureBackend {
+    /// Picks the backend from `BPTIMER_CAPTURE_BACKEND` (`windivert` or `pcap`),
+    /// falling back to the platform default.
+    /// `BPTIMER_PCAP_DEVICE` overrides the libpcap device.
+    /// `BPTIMER_REPLAY_FILE` replays a recording instead of capturing live traffic, as fast as
+    /// possible unless `BPTIMER_REPLAY_REALTIME` is set.
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        if let Some(path) = var("BPTIMER_REPLAY_FILE") {
+            let timing = if var("BPTIMER_REPLAY_REALTIME").is_some() {
+                ReplayTiming::Original
+            } else {
+                ReplayTiming::AsFastAsPossible
+            };
+            return CaptureBackend::Replay {
+                path: PathBuf::from(path),
+                timing,
+            };
+        }
+        let pcap_device = var("BPTIMER_PCAP_DEVICE");
+        match var("BPTIMER_CAPTURE_BACKEND").as_deref() {
+            Some("windivert") => CaptureBackend::WinDivert,
+            Some("pcap") => CaptureBackend::Pcap {
+                device: pcap_device,
+            },
+            Some(other) => {
+                warn!("Unknown capture backend '{other}', using the platform default");
+                Self::default()
+            }
+            None => match Self::default() {
+                CaptureBackend::Pcap { .. } => CaptureBackend::Pcap {
+                    device: pcap_device,
+                },
+                backend => backend,
+            },
+        }
+    }
+
+    pub fn open(&self) -> Result<Box<dyn CaptureSource>, String> {
+        match self {
+            #[cfg(windows)]
+            CaptureBackend::WinDivert => Ok(Box::new(
+                crate::packets::windivert_source::WinDivertSource::open()?,
+            )),
+            #[cfg(not(windows))]
+            CaptureBackend::WinDivert => Err("WinDivert is only available on Windows".to_string()),
+            #[cfg(not(windows))]
+            CaptureBackend::Pcap { device } => Ok(Box::new(
+                crate::packets::pcap_source::PcapSource::open(device.as_deref())?,
+            )),
+            #[cfg(windows)]
+            CaptureBackend::Pcap { .. } => Err("libpcap capture is not available on Windows".to_string()),
+            CaptureBackend::Replay { path, timing } => {
+                Ok(Box::new(ReplaySource::open(path, *timing)?))
+            }
+        }
+    }
+}
+
+// https://www.tcpdump.org/linktypes.html
+pub mod link_type {
+    pub const NULL: u32 = 0;
+    pub const ETHERNET: u32 = 1;
+    pub const RAW_BSD: u32 = 12;
+    pub const RAW: u32 = 101;
+    pub const LOOP: u32 = 108;
+    pub const LINUX_SLL: u32 = 113;
+    pub const IPV4: u32 = 228;
+    pub const LINUX_SLL2: u32 = 276;
+}
+
+/// Strips the link layer header of a captured frame and returns the IP packet.
+pub fn ip_payload(link_type: u32, frame: &[u8]) -> Option<&[u8]> {
+    const ETHERTYPE_VLAN: [u8; 2] = [0x81, 0x00];
+    const ETHERTYPE_QINQ: [u8; 2] = [0x88, 0xa8];
+
+    let offset = match link_type {
+        link_type::RAW | link_type::RAW_BSD | link_type::IPV4 => 0,
+        link_type::NULL | link_type::LOOP => 4,
+        link_type::LINUX_SLL => 16,
+        link_type::LINUX_SLL2 => 20,
+        link_type::ETHERNET => {
+            let mut offset = 14;
+            while frame.len() >= offset
+                && (frame[offset - 2..offset] == ETHERTYPE_VLAN
+                    || frame[offset - 2..offset] == ETHERTYPE_QINQ)
+            {
+                offset += 4;
+            }
+            offset
+        }
+        _ => return None,
+    };
+    frame.get(offset..)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packets::capture_source::{CaptureBackend, ip_payload, link_type};
+    use crate::packets::replay_source::ReplayTiming;
+    use std::path::PathBuf;
+
+    fn from_vars(vars: &[(&str, &str)]) -> CaptureBackend {
+        CaptureBackend::from_vars(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| (*value).to_string())
+        })
+    }
+
+    #[test]
+    fn test_backend_from_vars() {
+        assert_eq!(
+            from_vars(&[("BPTIMER_REPLAY_FILE", "a.pcapng"), ("BPTIMER_CAPTURE_BACKEND", "pcap")]),
+            CaptureBackend::Replay {
+                path: PathBuf::from("a.pcapng"),
+                timing: ReplayTiming::AsFastAsPossible,
+            }
+        );
+        assert_eq!(
+            from_vars(&[("BPTIMER_REPLAY_FILE", "a.pcap"), ("BPTIMER_REPLAY_REALTIME", "1")]),
+            CaptureBackend::Replay {
+                path: PathBuf::from("a.pcap"),
+                timing: ReplayTiming::Original,
+            }
+        );
+        assert_eq!(
+            from_vars(&[("BPTIMER_CAPTURE_BACKEND", "pcap"), ("BPTIMER_PCAP_DEVICE", "eth0")]),
+            CaptureBackend::Pcap {
+                device: Some("eth0".to_string()),
+            }
+        );
+        assert_eq!(
+            from_vars(&[("BPTIMER_CAPTURE_BACKEND", "windivert")]),
+            CaptureBackend::WinDivert
+        );
+        assert_eq!(from_vars(&[("BPTIMER_CAPTURE_BACKEND", "npcap")]), CaptureBackend::default());
+        assert_eq!(from_vars(&[]), CaptureBackend::default());
+    }
+
+    #[test]
+    fn test_ip_payload_strips_link_headers() {
+        let ip = [0x45, 0x00, 0x00, 0x14];
+        let ethernet = [&[0u8; 12][..], &[0x08, 0x00], &ip].concat();
+        assert_eq!(ip_payload(link_type::ETHERNET, &ethernet), Some(&ip[..]));
+        let vlan = [&[0u8; 12][..], &[0x81, 0x00, 0x00, 0x07, 0x08, 0x00], &ip].concat();
+        assert_eq!(ip_payload(link_type::ETHERNET, &vlan), Some(&ip[..]));
+        let sll = [&[0u8; 16][..], &ip].concat();
+        assert_eq!(ip_payload(link_type::LINUX_SLL, &sll), Some(&ip[..]));
+        assert_eq!(ip_payload(link_type::RAW, &ip), Some(&ip[..]));
+        assert_eq!(ip_payload(link_type::ETHERNET, &[0u8; 10]), None);
+        assert_eq!(ip_payload(147, &ip), None);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::packets::utils::Server;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use specta::Type;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub static CAPTURE_STATS: Lazy<Mutex<CaptureStats>> =
+    Lazy::new(|| Mutex::new(CaptureStats::default()));
+
+/// Health counters of the capture pipeline, used to tell why nothing is being reported.
+#[derive(Default)]
+pub struct CaptureStats {
+    pub source: Option<&'static str>,
+    pub source_error: Option<String>,
+    pub server: Option<Server>,
+    pub server_detected_at: Option<Instant>,
+    pub packets_seen: u64,
+    pub bytes_seen: u64,
+    pub reassembly_gaps: u64,
+    pub frames_decoded: u64,
+    pub frame_failures: u64,
+    pub zstd_failures: u64,
+    pub decode_failures: HashMap<&'static str, u64>,
+    pub last_packet_at: Option<Instant>,
+    pub last_frame_at: Option<Instant>,
+    /// Stall timeout of the watchdog, `None` when it isn't running.
+    pub stall_timeout: Option<Duration>,
+}
+
+impl CaptureStats {
+    pub fn set_server(&mut self, server: Option<Server>) {
+        self.server = server;
+        self.server_detected_at = server.map(|_| Instant::now());
+    }
+
+    /// Time without decoded frames since the game connection was detected, `None` while
+    /// no connection is known.
+    pub fn stalled_for(&self) -> Option<Duration> {
+        let server_detected_at = self.server_detected_at?;
+        let last_activity = self
+            .last_frame_at
+            .map_or(server_detected_at, |last_frame_at| last_frame_at.max(server_detected_at));
+        Some(last_activity.elapsed())
+    }
+
+    pub fn record_packet(&mut self, len: usize) {
+        self.packets_seen += 1;
+        self.bytes_seen += len as u64;
+        self.last_packet_at = Some(Instant::now());
+    }
+
+    pub fn record_frame(&mut self) {
+        self.frames_decoded += 1;
+        self.last_frame_at = Some(Instant::now());
+    }
+
+    pub fn record_decode_failure(&mut self, pkt_name: &'static str) {
+        *self.decode_failures.entry(pkt_name).or_default() += 1;
+    }
+
+    pub fn status(&self) -> CaptureStatus {
+        CaptureStatus {
+            source: self.source.map(str::to_string),
+            source_error: self.source_error.clone(),
+            server: self.server.map(|server| server.to_string()),
+            packets_seen: self.packets_seen,
+            bytes_seen: self.bytes_seen,
+            reassembly_gaps: self.reassembly_gaps,
+            frames_decoded: self.frames_decoded,
+            frame_failures: self.frame_failures,
+            zstd_failures: self.zstd_failures,
+            decode_failures: self
+                .decode_failures
+                .iter()
+                .map(|(pkt_name, count)| ((*pkt_name).to_string(), *count))
+                .collect(),
+            ms_since_last_packet: self.last_packet_at.map(|at| at.elapsed().as_millis() as u64),
+            ms_since_last_frame: self.last_frame_at.map(|at| at.elapsed().as_millis() as u64),
+            stall_timeout_secs: self.stall_timeout.map(|timeout| timeout.as_secs()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct CaptureStatus {
+    /// Name of the open capture backend, `None` if it failed to open.
+    pub source: Option<String>,
+    pub source_error: Option<String>,
+    /// Detected game server connection.
+    pub server: Option<String>,
+    pub packets_seen: u64,
+    pub bytes_seen: u64,
+    pub reassembly_gaps: u64,
+    pub frames_decoded: u64,
+    /// Reassembled frames that failed to decode, counted instead of `frames_decoded`.
+    pub frame_failures: u64,
+    pub zstd_failures: u64,
+    /// Protobuf decode failures keyed by message name.
+    pub decode_failures: HashMap<String, u64>,
+    pub ms_since_last_packet: Option<u64>,
+    pub ms_since_last_frame: Option<u64>,
+    /// Time without decoded frames after which the capture restarts itself, `None` when the
+    /// watchdog is off.
+    pub stall_timeout_secs: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packets::capture_stats::CaptureStats;
+    use crate::packets::utils::Server;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_counters() {
+        let mut stats = CaptureStats::default();
+        stats.record_packet(100);
+        stats.record_packet(20);
+        stats.record_frame();
+        stats.frame_failures += 1;
+        stats.record_decode_failure("SyncNearEntities");
+        stats.record_decode_failure("SyncNearEntities");
+        let status = stats.status();
+        assert_eq!((status.packets_seen, status.bytes_seen), (2, 120));
+        assert_eq!((status.frames_decoded, status.frame_failures), (1, 1));
+        assert_eq!(status.decode_failures.get("SyncNearEntities"), Some(&2));
+        assert!(status.ms_since_last_frame.is_some());
+        assert_eq!(status.stall_timeout_secs, None);
+        stats.stall_timeout = Some(Duration::from_secs(90));
+        assert_eq!(stats.status().stall_timeout_secs, Some(90));
+    }
+
+    #[test]
+    fn test_stalled_for() {
+        let mut stats = CaptureStats {
+            last_frame_at: Some(Instant::now() - Duration::from_secs(600)),
+            ..Default::default()
+        };
+        // not stalled without a known game connection
+        assert_eq!(stats.stalled_for(), None);
+
+        stats.set_server(Some(Server::new([10, 0, 0, 1], 5003, [192, 168, 0, 2], 50000)));
+        stats.server_detected_at = Some(Instant::now() - Duration::from_secs(60));
+        // frames from before the detection don't count
+        assert!(stats.stalled_for().unwrap() >= Duration::from_secs(60));
+        stats.last_frame_at = Some(Instant::now() - Duration::from_secs(10));
+        let stalled_for = stats.stalled_for().unwrap();
+        assert!(stalled_for >= Duration::from_secs(10) && stalled_for < Duration::from_secs(60));
+        stats.record_frame();
+        assert!(stats.stalled_for().unwrap() < Duration::from_secs(10));
+
+        stats.set_server(None);
+        assert_eq!(stats.stalled_for(), None);
+    }
+}
@@ -0,0 +1,230 @@
+use blueprotobuf_lib::wire;
+use log::info;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use specta::Type;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const MAX_SAMPLES: usize = 3;
+const MAX_SAMPLE_BYTES: usize = 4096;
+// payload sizes are bucketed by powers of four from 16 bytes to 64 kb, larger ones share the last bucket
+const SIZE_BUCKET_LIMITS: [u64; 8] = [16, 64, 256, 1024, 4096, 16384, 65536, u64::MAX];
+
+pub static OPCODE_DISCOVERY: Lazy<Mutex<OpcodeDiscovery>> =
+    Lazy::new(|| Mutex::new(OpcodeDiscovery::default()));
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct PayloadSample {
+    pub size: u32,
+    /// Hex of the payload, cut off after `MAX_SAMPLE_BYTES` bytes.
+    pub hex: String,
+    /// Schemaless protobuf dump of the same bytes.
+    pub wire_dump: String,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct SizeBucket {
+    /// Inclusive upper bound of the bucket, `None` for the last one.
+    pub up_to: Option<u32>,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct UnknownMethod {
+    pub method_id: u32,
+    pub count: u64,
+    pub min_size: u32,
+    pub max_size: u32,
+    pub mean_size: u32,
+    pub sizes: Vec<SizeBucket>,
+    pub samples: Vec<PayloadSample>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct OpcodeDiscoveryReport {
+    pub enabled: bool,
+    pub methods: Vec<UnknownMethod>,
+}
+
+#[derive(Default)]
+struct MethodStats {
+    count: u64,
+    min_size: usize,
+    max_size: usize,
+    total_size: u64,
+    size_buckets: [u64; SIZE_BUCKET_LIMITS.len()],
+    samples: Vec<PayloadSample>,
+}
+
+/// Histogram of notify method ids missing from the method table, with payload samples,
+/// to work out new opcodes after a game patch.
+#[derive(Default)]
+pub struct OpcodeDiscovery {
+    directory: Option<PathBuf>,
+    enabled: bool,
+    methods: BTreeMap<u32, MethodStats>,
+}
+
+impl OpcodeDiscovery {
+    pub fn configure(&mut self, directory: PathBuf) {
+        self.directory = Some(directory);
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        info!("Opcode discovery {}", if enabled { "enabled" } else { "disabled" });
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn record(&mut self, method_id: u32, payload: &[u8]) {
+        if !self.enabled {
+            return;
+        }
+        let stats = self.methods.entry(method_id).or_default();
+        let size = payload.len();
+        stats.min_size = if stats.count == 0 { size } else { stats.min_size.min(size) };
+        stats.max_size = stats.max_size.max(size);
+        stats.count += 1;
+        stats.total_size += size as u64;
+        let bucket = SIZE_BUCKET_LIMITS
+            .iter()
+            .position(|&limit| size as u64 <= limit)
+            .unwrap_or(SIZE_BUCKET_LIMITS.len() - 1);
+        stats.size_buckets[bucket] += 1;
+        if stats.samples.len() < MAX_SAMPLES {
+            stats.samples.push(PayloadSample {
+                size: size as u32,
+                hex: to_hex(&payload[..size.min(MAX_SAMPLE_BYTES)]),
+                wire_dump: wire::dump(&payload[..size.min(MAX_SAMPLE_BYTES)]),
+            });
+        }
+    }
+
+    pub fn report(&self) -> OpcodeDiscoveryReport {
+        let methods = self
+            .methods
+            .iter()
+            .map(|(&method_id, stats)| UnknownMethod {
+                method_id,
+                count: stats.count,
+                min_size: stats.min_size as u32,
+                max_size: stats.max_size as u32,
+                mean_size: (stats.total_size / stats.count.max(1)) as u32,
+                sizes: SIZE_BUCKET_LIMITS
+                    .iter()
+                    .zip(stats.size_buckets)
+                    .filter(|(_, count)| *count > 0)
+                    .map(|(&limit, count)| SizeBucket {
+                        up_to: u32::try_from(limit).ok(),
+                        count,
+                    })
+                    .collect(),
+                samples: stats.samples.clone(),
+            })
+            .collect();
+        OpcodeDiscoveryReport {
+            enabled: self.enabled,
+            methods,
+        }
+    }
+
+    /// Writes the report as JSON and returns the path of the file.
+    pub fn export(&self) -> Result<PathBuf, String> {
+        let directory = self
+            .directory
+            .as_ref()
+            .ok_or_else(|| "Opcode discovery has no output directory".to_string())?;
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create {}: {e}", directory.display()))?;
+        let path = directory.join(format!(
+            "discovery-{}.json",
+            chrono::Local::now().format("%Y-%m-%d_%H-%M-%S")
+        ));
+        let json = serde_json::to_string_pretty(&self.report()).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        info!("Exported opcode discovery to {}", path.display());
+        Ok(path)
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().fold(String::with_capacity(data.len() * 2), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packets::opcode_discovery::{MAX_SAMPLE_BYTES, MAX_SAMPLES, OpcodeDiscovery};
+    use std::fs;
+
+    #[test]
+    fn test_disabled_discovery_records_nothing() {
+        let mut discovery = OpcodeDiscovery::default();
+        discovery.record(0x99, b"payload");
+        assert!(discovery.report().methods.is_empty());
+    }
+
+    #[test]
+    fn test_size_histogram() {
+        let mut discovery = OpcodeDiscovery::default();
+        discovery.set_enabled(true);
+        for size in [0, 16, 17, 64, 100_000] {
+            discovery.record(0x99, &vec![0u8; size]);
+        }
+        discovery.record(0x42, b"other");
+
+        let report = discovery.report();
+        assert_eq!(
+            report.methods.iter().map(|method| method.method_id).collect::<Vec<_>>(),
+            vec![0x42, 0x99]
+        );
+        let method = &report.methods[1];
+        assert_eq!((method.count, method.min_size, method.max_size), (5, 0, 100_000));
+        assert_eq!(method.mean_size, (16 + 17 + 64 + 100_000) / 5);
+        // bucket bounds are inclusive and empty buckets are left out
+        let buckets: Vec<_> = method.sizes.iter().map(|bucket| (bucket.up_to, bucket.count)).collect();
+        assert_eq!(buckets, vec![(Some(16), 2), (Some(64), 2), (None, 1)]);
+    }
+
+    #[test]
+    fn test_samples_are_capped() {
+        let mut discovery = OpcodeDiscovery::default();
+        discovery.set_enabled(true);
+        discovery.record(0x99, &[0x08, 0x96, 0x01]);
+        for _ in 0..MAX_SAMPLES {
+            discovery.record(0x99, &vec![0xab; MAX_SAMPLE_BYTES + 1]);
+        }
+
+        let samples = &discovery.report().methods[0].samples;
+        assert_eq!(samples.len(), MAX_SAMPLES);
+        assert_eq!((samples[0].size, samples[0].hex.as_str()), (3, "089601"));
+        assert_eq!(samples[1].size as usize, MAX_SAMPLE_BYTES + 1);
+        assert_eq!(samples[1].hex.len(), MAX_SAMPLE_BYTES * 2);
+    }
+
+    #[test]
+    fn test_export() {
+        let mut discovery = OpcodeDiscovery::default();
+        assert!(discovery.export().is_err());
+
+        let directory = std::env::temp_dir().join(format!("bptimer-discovery-{}", std::process::id()));
+        discovery.configure(directory.clone());
+        discovery.set_enabled(true);
+        discovery.record(0x99, b"payload");
+        let path = discovery.export().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(json["enabled"], true);
+        assert_eq!(json["methods"][0]["method_id"], 0x99);
+        assert_eq!(json["methods"][0]["samples"][0]["hex"], "7061796c6f6164");
+    }
+}
@@ -1,32 +1,196 @@
-#[derive(Debug)]
-pub struct ParseError;
+use blueprotobuf_lib::blueprotobuf;
+use bytes::Bytes;
+use std::fmt;
+use std::io;
 
-#[non_exhaustive]
-#[derive(Debug)]
-pub enum Pkt {
-    ServerChangeInfo,
-    SyncNearEntities = 0x00000006,  
-    SyncContainerData = 0x00000015,
-    SyncServerTime = 0x0000002b,
-    SyncToMeDeltaInfo = 0x0000002e,
-    SyncNearDeltaInfo = 0x0000002d,
+/// Why a frame was skipped while decoding a reassembled stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame ended before all of its header fields.
+    Truncated,
+    /// The size prefix is smaller than a header or larger than the remaining data.
+    InvalidSize { size: u32, remaining: usize },
+    UnknownFragment(u16),
+    ServiceMismatch(u64),
+    /// A notify method missing from the method table, with its still compressed payload.
+    UnknownMethod {
+        method_id: u32,
+        payload: Bytes,
+        is_zstd_compressed: bool,
+    },
+    Decompress(String),
+    DecompressedTooLarge { limit: usize },
+    /// `FrameDown`/`FrameUp` nesting beyond the supported depth.
+    TooDeep { depth: usize },
 }
 
-impl TryFrom<u32> for Pkt {
-    type Error = ParseError;
-
-    fn try_from(pkt: u32) -> Result<Self, Self::Error> {
-        match pkt {
-            0x00000006 => Ok(Pkt::SyncNearEntities),
-            0x00000015 => Ok(Pkt::SyncContainerData),
-            0x0000002b => Ok(Pkt::SyncServerTime),
-            0x0000002e => Ok(Pkt::SyncToMeDeltaInfo),
-            0x0000002d => Ok(Pkt::SyncNearDeltaInfo),
-            _ => Err(ParseError),
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Truncated => write!(f, "truncated frame"),
+            FrameError::InvalidSize { size, remaining } => {
+                write!(f, "invalid frame size {size} with {remaining} bytes remaining")
+            }
+            FrameError::UnknownFragment(fragment_type) => {
+                write!(f, "unknown fragment type {fragment_type}")
+            }
+            FrameError::ServiceMismatch(service_uuid) => {
+                write!(f, "unexpected service uuid {service_uuid:#x}")
+            }
+            FrameError::UnknownMethod { method_id, .. } => {
+                write!(f, "unknown method id {method_id:#x}")
+            }
+            FrameError::Decompress(e) => write!(f, "zstd decompression failed: {e}"),
+            FrameError::DecompressedTooLarge { limit } => {
+                write!(f, "decompressed payload exceeds {limit} bytes")
+            }
+            FrameError::TooDeep { depth } => write!(f, "nested frames exceed depth {depth}"),
         }
     }
 }
 
+impl std::error::Error for FrameError {}
+
+impl From<io::Error> for FrameError {
+    // `BinaryReader` only fails when it runs out of data
+    fn from(_: io::Error) -> Self {
+        FrameError::Truncated
+    }
+}
+
+/// Header of an RPC call sent by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcCall {
+    pub service_uuid: u64,
+    pub stub_id: u32,
+    pub method_id: u32,
+}
+
+/// `serde::Serialize` when the `journal-json` feature enables the serde impls of
+/// `blueprotobuf`, no bound otherwise.
+#[cfg(feature = "journal-json")]
+pub trait MaybeSerialize: serde::Serialize {}
+#[cfg(feature = "journal-json")]
+impl<T: serde::Serialize> MaybeSerialize for T {}
+#[cfg(not(feature = "journal-json"))]
+pub trait MaybeSerialize {}
+#[cfg(not(feature = "journal-json"))]
+impl<T> MaybeSerialize for T {}
+
+/// A decoded notify message.
+pub trait NotifyMessage: prost::Message + Default + fmt::Debug + MaybeSerialize + 'static {}
+impl<M: prost::Message + Default + fmt::Debug + MaybeSerialize + 'static> NotifyMessage for M {}
+
+/// Receives a decoded notify message without knowing its concrete type.
+pub trait NotifyHook {
+    type Output;
+
+    fn on_message<M: NotifyMessage>(&mut self, message: M) -> Self::Output;
+}
+
+/// Declares the notify methods of the world notification service.
+/// Each entry is `method id => message type`, optionally followed by `=> handler`. The `Pkt`
+/// variant is named after the `blueprotobuf` message it carries, and each handler becomes a
+/// method of `NotifyHandler` taking that message.
+macro_rules! notify_methods {
+    ($($method_id:literal => $message:ident $(=> $handler:ident)?,)*) => {
+        #[non_exhaustive]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Pkt {
+            ServerChangeInfo,
+            /// A request sent by the client, the payload is the request message.
+            Call(RpcCall),
+            /// The server's response to a call, the payload is the response message.
+            /// `call` is `None` when the request wasn't captured.
+            Return { stub_id: u32, call: Option<RpcCall> },
+            $($message,)*
+        }
+
+        /// Applies the notify messages that have a handler in the method table.
+        pub trait NotifyHandler {
+            $($(fn $handler(&mut self, message: blueprotobuf::$message);)?)*
+        }
+
+        impl Pkt {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    Pkt::ServerChangeInfo => "ServerChangeInfo",
+                    Pkt::Call(_) => "Call",
+                    Pkt::Return { .. } => "Return",
+                    $(Pkt::$message => stringify!($message),)*
+                }
+            }
+
+            /// The method id of a notify message, `None` for the other packets.
+            pub fn method_id(&self) -> Option<u32> {
+                match self {
+                    $(Pkt::$message => Some($method_id),)*
+                    _ => None,
+                }
+            }
+
+            /// Decodes the payload of a notify message and passes it to `hook`.
+            /// Returns `None` for packets that aren't notify messages.
+            pub fn decode_notify<H: NotifyHook>(
+                &self,
+                data: &[u8],
+                hook: &mut H,
+            ) -> Option<Result<H::Output, prost::DecodeError>> {
+                match self {
+                    $(Pkt::$message => Some(
+                        <blueprotobuf::$message as prost::Message>::decode(data)
+                            .map(|message| hook.on_message(message)),
+                    ),)*
+                    _ => None,
+                }
+            }
+
+            /// Decodes the payload of a notify message and passes it to its handler in
+            /// `handler`, messages without one are only decoded.
+            /// Returns `None` for packets that aren't notify messages.
+            pub fn handle_notify<H: NotifyHandler>(
+                &self,
+                data: &[u8],
+                handler: &mut H,
+            ) -> Option<Result<(), prost::DecodeError>> {
+                match self {
+                    $(Pkt::$message => Some(
+                        <blueprotobuf::$message as prost::Message>::decode(data)
+                            .map(|_message| { $(handler.$handler(_message);)? }),
+                    ),)*
+                    _ => None,
+                }
+            }
+        }
+
+        /// Fails with the method id when it is missing from the method table.
+        impl TryFrom<u32> for Pkt {
+            type Error = u32;
+
+            fn try_from(method_id: u32) -> Result<Self, Self::Error> {
+                match method_id {
+                    $($method_id => Ok(Pkt::$message),)*
+                    _ => Err(method_id),
+                }
+            }
+        }
+    };
+}
+
+// Ids marked as inferred follow the declaration order of the messages in the schema and
+// haven't been seen in a capture yet, unknown ids show up in opcode discovery.
+notify_methods! {
+    0x00000006 => SyncNearEntities => on_sync_near_entities,
+    0x00000008 => SyncSceneEvents, // inferred
+    0x00000015 => SyncContainerData => on_sync_container_data,
+    0x00000016 => SyncContainerDirtyData => on_sync_container_dirty_data,
+    0x00000017 => SyncDungeonData,
+    0x0000002b => SyncServerTime,
+    0x0000002d => SyncNearDeltaInfo => on_sync_near_delta_info,
+    0x0000002e => SyncToMeDeltaInfo => on_sync_to_me_delta_info,
+    0x0000002f => NotifyClientKickOff => on_client_kick_off, // inferred
+}
+
 #[repr(u16)]
 #[non_exhaustive]
 #[derive(Debug)]
@@ -55,44 +219,25 @@ impl From<u16> for FragmentType {
     }
 }
 
-/*
-Pkt::DeathNotify
-Pkt::IdentityGaugeChangeNotify
-Pkt::IdentityStanceChangeNotify
-Pkt::InitEnv
-Pkt::InitPC
-Pkt::InitItem
-Pkt::MigrationExecute
-Pkt::NewPC
-Pkt::NewVehicle
-Pkt::NewNpc
-Pkt::NewNpcSummon
-Pkt::NewProjectile
-Pkt::NewTrap
-Pkt::ParalyzationStateNotify
-Pkt::RaidBegin
-Pkt::RaidBossKillNotify
-Pkt::RaidResult
-Pkt::RemoveObject
-Pkt::SkillCastNotify
-Pkt::SkillCooldownNotify
-Pkt::SkillStartNotify
-Pkt::SkillStageNotify
-Pkt::SkillDamageAbnormalMoveNotify
-Pkt::SkillDamageNotify
-Pkt::PartyInfo
-Pkt::PartyLeaveResult
-Pkt::PartyStatusEffectAddNotify
-Pkt::PartyStatusEffectRemoveNotify
-Pkt::PartyStatusEffectResultNotify
-Pkt::StatusEffectAddNotify
-Pkt::StatusEffectDurationNotify
-Pkt::StatusEffectRemoveNotify
-Pkt::TriggerBossBattleStatus
-Pkt::TriggerStartNotify
-Pkt::ZoneMemberLoadStatusNotify
-Pkt::ZoneObjectUnpublishNotify
-Pkt::StatusEffectSyncDataNotify
-Pkt::TroopMemberUpdateMinNotify
-Pkt::NewTransit
- */
+#[cfg(test)]
+mod tests {
+    use crate::packets::opcodes::{Pkt, RpcCall};
+
+    #[test]
+    fn test_method_ids_round_trip() {
+        let pkts: Vec<Pkt> = (0..0x100).filter_map(|id| Pkt::try_from(id).ok()).collect();
+        assert!(pkts.contains(&Pkt::SyncSceneEvents));
+        assert!(pkts.contains(&Pkt::NotifyClientKickOff));
+        for pkt in pkts {
+            assert_eq!(pkt.method_id().map(Pkt::try_from), Some(Ok(pkt)));
+        }
+        assert_eq!(Pkt::try_from(0x7fff_ffff), Err(0x7fff_ffff));
+        assert_eq!(Pkt::ServerChangeInfo.method_id(), None);
+        let call = RpcCall {
+            service_uuid: 0x63335342,
+            stub_id: 1,
+            method_id: 0x06,
+        };
+        assert_eq!(Pkt::Call(call).method_id(), None);
+    }
+}
@@ -1,28 +1,72 @@
 use crate::packets;
+use crate::packets::capture_source::{CaptureBackend, CaptureSource, NextPacket};
+use crate::packets::capture_stats::CAPTURE_STATS;
 use crate::packets::opcodes::Pkt;
-use crate::packets::packet_process::process_packet;
-use crate::packets::utils::{BinaryReader, Server, TCPReassembler};
+use crate::packets::packet_process::{PendingCalls, process_packet};
+use crate::packets::session_recorder::SESSION_RECORDER;
+use crate::packets::utils::{BinaryReader, Server, StreamDirection, TCPReassembler};
+use bytes::Bytes;
 use etherparse::NetSlice::Ipv4;
 use etherparse::SlicedPacket;
 use etherparse::TransportSlice::Tcp;
 use log::{debug, error, info, warn};
 use once_cell::sync::OnceCell;
-use tokio::sync::watch;
-use windivert::WinDivert;
-use windivert::prelude::WinDivertFlags;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 
 static RESTART_SENDER: OnceCell<watch::Sender<bool>> = OnceCell::new();
 
-pub fn start_capture() -> tokio::sync::mpsc::Receiver<(packets::opcodes::Pkt, Vec<u8>)> {
+const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(5);
+// a live source only closes when its handle died, it is reopened after this delay
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+const SOURCE_QUEUE_SIZE: usize = 4096;
+
+pub fn start_capture(
+    backend: CaptureBackend,
+) -> tokio::sync::mpsc::Receiver<(StreamDirection, packets::opcodes::Pkt, Bytes)> {
     let (packet_sender, packet_receiver) =
-        tokio::sync::mpsc::channel::<(packets::opcodes::Pkt, Vec<u8>)>(1);
+        tokio::sync::mpsc::channel::<(StreamDirection, packets::opcodes::Pkt, Bytes)>(1);
     let (restart_sender, mut restart_receiver) = watch::channel(false);
     RESTART_SENDER.set(restart_sender.clone()).ok();
+    let stall_timeout = stall_timeout_from_vars(&backend, |name| std::env::var(name).ok());
+    CAPTURE_STATS.lock().unwrap().stall_timeout = stall_timeout;
+    if let Some(stall_timeout) = stall_timeout {
+        tauri::async_runtime::spawn(run_watchdog(stall_timeout));
+    }
     tauri::async_runtime::spawn(async move {
+        // kept across restarts, the game connection outlives the capture source
+        let mut known_server: Option<Server> = None;
         loop {
-            read_packets(&packet_sender, &mut restart_receiver).await;
-            while !*restart_receiver.borrow() {
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            match backend.open() {
+                Ok(source) => {
+                    info!("Capture source {} opened", source.name());
+                    {
+                        let mut stats = CAPTURE_STATS.lock().unwrap();
+                        stats.source = Some(source.name());
+                        stats.source_error = None;
+                    }
+                    let mut source_packets = spawn_source_reader(source);
+                    read_packets(
+                        &mut source_packets,
+                        &packet_sender,
+                        &mut restart_receiver,
+                        &mut known_server,
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    error!("Failed to open capture source {backend:?}: {e}");
+                    let mut stats = CAPTURE_STATS.lock().unwrap();
+                    stats.source = None;
+                    stats.source_error = Some(e);
+                }
+            }
+            let wait_for_restart = restart_receiver.wait_for(|restart| *restart);
+            if matches!(backend, CaptureBackend::Replay { .. }) {
+                let _ = wait_for_restart.await;
+            } else if tokio::time::timeout(REOPEN_DELAY, wait_for_restart).await.is_err() {
+                warn!("Capture source closed, reopening {backend:?}");
             }
             let _ = restart_sender.send(false);
         }
@@ -30,32 +74,106 @@ pub fn start_capture() -> tokio::sync::mpsc::Receiver<(packets::opcodes::Pkt, Ve
     packet_receiver
 }
 
-#[allow(clippy::too_many_lines)]
-async fn read_packets(
-    packet_sender: &tokio::sync::mpsc::Sender<(packets::opcodes::Pkt, Vec<u8>)>,
-    restart_receiver: &mut watch::Receiver<bool>,
-) {
-    let windivert = match WinDivert::network(
-        "!loopback && ip && tcp",
-        0,
-        WinDivertFlags::new().set_sniff(),
-    ) {
-        Ok(windivert_handle) => {
-            info!("WinDivert handle opened!");
-            Some(windivert_handle)
-        }
+/// Asks the capture task to close its source and open it again.
+/// The running source is dropped right away, even while it isn't receiving packets.
+pub fn restart_capture() -> Result<(), String> {
+    let restart_sender = RESTART_SENDER
+        .get()
+        .ok_or_else(|| "Capture has not been started".to_string())?;
+    if !*restart_sender.borrow() {
+        info!("Restarting capture");
+        restart_sender
+            .send(true)
+            .map_err(|_| "Capture task is not running".to_string())?;
+    }
+    Ok(())
+}
+
+/// Reads the watchdog stall timeout from `BPTIMER_WATCHDOG_SECS`, `0` disables the watchdog.
+/// A replay isn't watched: once it ended nothing more gets decoded, and restarting it would
+/// play the whole session again.
+fn stall_timeout_from_vars(backend: &CaptureBackend, var: impl Fn(&str) -> Option<String>) -> Option<Duration> {
+    if matches!(backend, CaptureBackend::Replay { .. }) {
+        return None;
+    }
+    let Some(value) = var("BPTIMER_WATCHDOG_SECS") else {
+        return Some(DEFAULT_STALL_TIMEOUT);
+    };
+    match value.parse::<u64>() {
+        Ok(0) => None,
+        Ok(secs) => Some(Duration::from_secs(secs)),
         Err(e) => {
-            error!("Failed to initialize WinDivert: {}", e);
-            return;
+            warn!("Invalid BPTIMER_WATCHDOG_SECS '{value}': {e}, using the default");
+            Some(DEFAULT_STALL_TIMEOUT)
         }
     }
-    .expect("Failed to initialize WinDivert");
-    let mut windivert_buffer = vec![0u8; 10 * 1024 * 1024];
-    let mut known_server: Option<Server> = None;
-    let mut tcp_reassembler: TCPReassembler = TCPReassembler::new();
-    while let Ok(packet) = windivert.recv(Some(&mut windivert_buffer)) {
+}
 
-        let Ok(network_slices) = SlicedPacket::from_ip(packet.data.as_ref()) else {
+/// Restarts the capture when no frames were decoded for `stall_timeout` while the game
+/// connection is known, e.g. after the capture handle died or the reassembler wedged.
+async fn run_watchdog(stall_timeout: Duration) {
+    let mut interval = tokio::time::interval(WATCHDOG_INTERVAL);
+    loop {
+        interval.tick().await;
+        let stalled_for = CAPTURE_STATS.lock().unwrap().stalled_for();
+        if let Some(stalled_for) = stalled_for.filter(|stalled_for| *stalled_for >= stall_timeout) {
+            warn!("No frames decoded for {}s, restarting capture", stalled_for.as_secs());
+            if let Err(e) = restart_capture() {
+                warn!("Watchdog failed to restart capture: {e}");
+            }
+        }
+    }
+}
+
+/// Reads `source` on its own thread, since live sources block in the capture driver and
+/// replay sleeps to reproduce the original timing. The thread ends with the source or
+/// once the returned receiver is dropped.
+fn spawn_source_reader(mut source: Box<dyn CaptureSource>) -> mpsc::Receiver<Bytes> {
+    let (sender, receiver) = mpsc::channel(SOURCE_QUEUE_SIZE);
+    let spawned = std::thread::Builder::new()
+        .name(format!("capture-{}", source.name()))
+        .spawn(move || loop {
+            match source.next_packet() {
+                NextPacket::Packet(packet) => {
+                    if sender.blocking_send(Bytes::copy_from_slice(packet)).is_err() {
+                        break;
+                    }
+                }
+                NextPacket::Idle if sender.is_closed() => break,
+                NextPacket::Idle => {}
+                NextPacket::Closed => break,
+            }
+        });
+    if let Err(e) = spawned {
+        error!("Failed to start the capture reader thread: {e}");
+    }
+    receiver
+}
+
+#[allow(clippy::too_many_lines)]
+async fn read_packets(
+    source_packets: &mut mpsc::Receiver<Bytes>,
+    packet_sender: &tokio::sync::mpsc::Sender<(StreamDirection, packets::opcodes::Pkt, Bytes)>,
+    restart_receiver: &mut watch::Receiver<bool>,
+    known_server: &mut Option<Server>,
+) {
+    // server -> client and client -> server are separate byte streams with their own sequence numbers
+    let mut downstream_reassembler = TCPReassembler::new();
+    let mut upstream_reassembler = TCPReassembler::new();
+    let mut pending_calls = PendingCalls::default();
+    // restarts the stall timer, the fresh reassemblers resync on the next frame header
+    CAPTURE_STATS.lock().unwrap().set_server(*known_server);
+    loop {
+        let packet = tokio::select! {
+            packet = source_packets.recv() => match packet {
+                Some(packet) => packet,
+                None => break,
+            },
+            _ = restart_receiver.wait_for(|restart| *restart) => break,
+        };
+        let packet = packet.as_ref();
+        CAPTURE_STATS.lock().unwrap().record_packet(packet.len());
+        let Ok(network_slices) = SlicedPacket::from_ip(packet) else {
             continue;
         };
         let Some(Ipv4(ip_packet)) = network_slices.net else {
@@ -71,9 +189,18 @@ async fn read_packets(
             tcp_packet.to_header().destination_port,
         );
 
-        if known_server != Some(curr_server) {
+        let direction = match *known_server {
+            Some(server) if curr_server == server => Some(StreamDirection::Downstream),
+            Some(server) if curr_server == server.reversed() => Some(StreamDirection::Upstream),
+            _ => None,
+        };
+        if let (Some(server), Some(_)) = (*known_server, direction) {
+            record_packet(server, packet);
+        }
+
+        let Some(direction) = direction else {
             let tcp_payload = tcp_packet.payload();
-            let mut tcp_payload_reader = BinaryReader::from(tcp_payload.to_vec());
+            let mut tcp_payload_reader = BinaryReader::from(Bytes::copy_from_slice(tcp_payload));
             if tcp_payload_reader.remaining() >= 10 {
                 match tcp_payload_reader.read_bytes(10) {
                     Ok(bytes) => {
@@ -105,13 +232,24 @@ async fn read_packets(
                                                 info!(
                                                     "Got Scene Server Address (by change): {curr_server}"
                                                 );
-                                                known_server = Some(curr_server);
-                                                tcp_reassembler.clear_reassembler(
-                                                    tcp_packet.sequence_number() as usize
-                                                        + tcp_payload_reader.len(),
+                                                *known_server = Some(curr_server);
+                                                CAPTURE_STATS
+                                                    .lock()
+                                                    .unwrap()
+                                                    .set_server(Some(curr_server));
+                                                upstream_reassembler.reset();
+                                                pending_calls.clear();
+                                                downstream_reassembler.clear_reassembler(
+                                                    tcp_packet
+                                                        .sequence_number()
+                                                        .wrapping_add(tcp_payload.len() as u32),
                                                 );
                                                 if let Err(err) = packet_sender
-                                                    .send((Pkt::ServerChangeInfo, Vec::new()))
+                                                    .send((
+                                                        StreamDirection::Downstream,
+                                                        Pkt::ServerChangeInfo,
+                                                        Bytes::new(),
+                                                    ))
                                                     .await
                                                 {
                                                     debug!("Failed to send packet: {err}");
@@ -145,94 +283,291 @@ async fn read_packets(
                     && tcp_payload[14..20] == SIGNATURE_2
                 {
                     info!("Got Scene Server Address by Login Return Packet: {curr_server}");
-                    known_server = Some(curr_server);
-                    tcp_reassembler.clear_reassembler(
-                        tcp_packet.sequence_number() as usize + tcp_payload.len(),
+                    *known_server = Some(curr_server);
+                    CAPTURE_STATS.lock().unwrap().set_server(Some(curr_server));
+                    upstream_reassembler.reset();
+                    pending_calls.clear();
+                    downstream_reassembler.clear_reassembler(
+                        tcp_packet.sequence_number().wrapping_add(tcp_payload.len() as u32),
                     );
                     if let Err(err) = packet_sender
-                        .send((Pkt::ServerChangeInfo, Vec::new()))
+                        .send((StreamDirection::Downstream, Pkt::ServerChangeInfo, Bytes::new()))
                         .await
                     {
                         debug!("Failed to send packet: {err}");
                     }
                 }
             }
+            if *known_server == Some(curr_server) {
+                // the packet that revealed the server is needed to detect it again on replay
+                SESSION_RECORDER
+                    .lock()
+                    .unwrap()
+                    .on_server_detected(curr_server, packet);
+            }
             continue;
+        };
+
+        let tcp_reassembler = match direction {
+            StreamDirection::Downstream => &mut downstream_reassembler,
+            StreamDirection::Upstream => &mut upstream_reassembler,
+        };
+        tcp_reassembler.push_segment(tcp_packet.sequence_number(), tcp_packet.payload());
+        while let Some(frame) = tcp_reassembler.next_frame() {
+            debug!("Processing {direction:?} packet: size={}", frame.len());
+            process_packet(frame, direction, packet_sender, &mut pending_calls).await;
         }
+        let gaps = tcp_reassembler.take_gap_count();
+        if gaps > 0 {
+            CAPTURE_STATS.lock().unwrap().reassembly_gaps += gaps;
+        }
+    }
+}
+
+fn record_packet(server: Server, packet: &[u8]) {
+    let mut recorder = SESSION_RECORDER.lock().unwrap();
+    if recorder.is_enabled() {
+        recorder.record(server, packet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::opcodes_models::{attr_type, Encounter, HpReport, LeftViewReason};
+    use crate::live::opcodes_process::process_notify;
+    use crate::packets::capture_source::CaptureBackend;
+    use crate::packets::opcodes::Pkt;
+    use crate::packets::packet_capture::{
+        read_packets, spawn_source_reader, stall_timeout_from_vars, DEFAULT_STALL_TIMEOUT,
+    };
+    use crate::packets::replay_source::{ReplaySource, ReplayTiming};
+    use crate::packets::session_recorder::SessionRecorder;
+    use crate::packets::utils::{Server, StreamDirection};
+    use blueprotobuf_lib::blueprotobuf;
+    use bytes::Bytes;
+    use etherparse::PacketBuilder;
+    use prost::Message;
+    use std::fs;
+    use std::time::Duration;
+    use tokio::sync::{mpsc, watch};
+
+    const SERVER_ADDR: [u8; 4] = [10, 0, 0, 1];
+    const CLIENT_ADDR: [u8; 4] = [192, 168, 0, 2];
+    const SERVER_PORT: u16 = 5003;
+    const CLIENT_PORT: u16 = 50000;
+    const SERVICE_UUID: u64 = 0x0000000063335342;
+    const BOSS_UID: i64 = 7;
+    // a crowdsourced world boss
+    const BOSS_ID: i32 = 10032;
 
-        if tcp_reassembler.next_seq.is_none() {
-            tcp_reassembler.next_seq = Some(tcp_packet.sequence_number() as usize);
+    fn tcp_packet(seq: u32, payload: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        PacketBuilder::ipv4(SERVER_ADDR, CLIENT_ADDR, 64)
+            .tcp(SERVER_PORT, CLIENT_PORT, seq, 65535)
+            .write(&mut packet, payload)
+            .unwrap();
+        packet
+    }
+
+    /// The login return the game server is detected from.
+    fn login_return() -> Vec<u8> {
+        let mut payload = vec![0u8; 98];
+        payload[..10].copy_from_slice(&[0x00, 0x00, 0x00, 0x62, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+        payload[14..20].copy_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x0a, 0x4e]);
+        payload
+    }
+
+    fn notify(method: Pkt, payload: &[u8]) -> Vec<u8> {
+        let method_id = method.method_id().unwrap();
+        let mut frame = ((payload.len() + 22) as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&2u16.to_be_bytes());
+        frame.extend_from_slice(&SERVICE_UUID.to_be_bytes());
+        frame.extend_from_slice(&0u32.to_be_bytes());
+        frame.extend_from_slice(&method_id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn attr(id: i32, value: u64) -> blueprotobuf::Attr {
+        let mut raw_data = Vec::new();
+        prost::encoding::encode_varint(value, &mut raw_data);
+        blueprotobuf::Attr {
+            id: Some(id),
+            raw_data: Some(raw_data),
         }
-        if tcp_reassembler
-            .next_seq
-            .unwrap()
-            .saturating_sub(tcp_packet.sequence_number() as usize)
-            == 0
-        {
-            tcp_reassembler.cache.insert(
-                tcp_packet.sequence_number() as usize,
-                Vec::from(tcp_packet.payload()),
-            );
+    }
+
+    fn boss_uuid() -> i64 {
+        (BOSS_UID << 16) | ((blueprotobuf::EEntityType::EntMonster as i64) << 6)
+    }
+
+    fn boss_hp(hp: u64, is_dead: bool) -> blueprotobuf::SyncNearDeltaInfo {
+        blueprotobuf::SyncNearDeltaInfo {
+            delta_infos: vec![blueprotobuf::AoiSyncDelta {
+                uuid: Some(boss_uuid()),
+                attrs: Some(blueprotobuf::AttrCollection {
+                    attrs: vec![attr(attr_type::ATTR_HP, hp)],
+                    ..Default::default()
+                }),
+                skill_effects: Some(blueprotobuf::SkillEffect {
+                    damages: vec![blueprotobuf::SyncDamageInfo {
+                        value: Some(100),
+                        is_dead: Some(is_dead),
+                        damage_pos: Some(blueprotobuf::Vector3 {
+                            x: Some(30.0),
+                            y: Some(40.0),
+                            z: Some(0.0),
+                        }),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
         }
-        let mut i = 0;
-        while tcp_reassembler
-            .cache
-            .contains_key(&tcp_reassembler.next_seq.unwrap())
-        {
-            i += 1;
-            if i % 1000 == 0 {
-                warn!(
-                    "Potential infinite loop in cache processing: iteration={i}, next_seq={:?}, cache_size={}, _data_len={}",
-                    tcp_reassembler.next_seq,
-                    tcp_reassembler.cache.len(),
-                    tcp_reassembler._data.len()
-                );
-            }
-            let seq = &tcp_reassembler.next_seq.unwrap();
-            let cached_tcp_data = tcp_reassembler.cache.get(seq).unwrap();
-            if tcp_reassembler._data.is_empty() {
-                tcp_reassembler._data = cached_tcp_data.clone();
-            } else {
-                tcp_reassembler._data.extend_from_slice(cached_tcp_data);
-            }
-            tcp_reassembler.next_seq = Some(seq.wrapping_add(cached_tcp_data.len()));
-            tcp_reassembler.cache.remove(seq);
+    }
+
+    /// A short boss fight as the server sends it: the local player's scene, the boss
+    /// appearing, taking damage, dying and disappearing.
+    fn boss_fight() -> Vec<(Pkt, Vec<u8>)> {
+        let local_player = blueprotobuf::SyncContainerData {
+            v_data: Some(blueprotobuf::CharSerialize {
+                char_id: Some(1),
+                scene_data: Some(blueprotobuf::SceneData {
+                    map_id: Some(8),
+                    line_id: Some(3),
+                    pos: Some(blueprotobuf::Position {
+                        x: Some(10.0),
+                        y: Some(20.0),
+                        z: Some(0.0),
+                        dir: None,
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+        };
+        let boss_appear = blueprotobuf::SyncNearEntities {
+            appear: vec![blueprotobuf::Entity {
+                uuid: Some(boss_uuid()),
+                attrs: Some(blueprotobuf::AttrCollection {
+                    attrs: vec![
+                        attr(attr_type::ATTR_ID, BOSS_ID as u64),
+                        attr(attr_type::ATTR_MAX_HP, 1000),
+                        attr(attr_type::ATTR_HP, 900),
+                    ],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            disappear: vec![],
+        };
+        let boss_disappear = blueprotobuf::SyncNearEntities {
+            appear: vec![],
+            disappear: vec![blueprotobuf::DisappearEntity {
+                uuid: Some(boss_uuid()),
+                r#type: Some(blueprotobuf::EDisappearType::EDisappearDead as i32),
+            }],
+        };
+        vec![
+            (Pkt::SyncContainerData, local_player.encode_to_vec()),
+            (Pkt::SyncNearEntities, boss_appear.encode_to_vec()),
+            (Pkt::SyncNearDeltaInfo, boss_hp(400, false).encode_to_vec()),
+            (Pkt::SyncNearDeltaInfo, boss_hp(0, true).encode_to_vec()),
+            (Pkt::SyncNearEntities, boss_disappear.encode_to_vec()),
+        ]
+    }
+
+    fn apply(messages: Vec<(Pkt, Bytes)>) -> Encounter {
+        let mut encounter = Encounter::default();
+        for (op, data) in messages {
+            process_notify(&mut encounter, op, data).unwrap();
         }
-        i = 0;
-        while tcp_reassembler._data.len() > 4 {
-            i += 1;
-            if i % 1000 == 0 {
-                let sample = &tcp_reassembler._data[..tcp_reassembler._data.len().min(32)];
-                warn!(
-                    "Potential infinite loop in _data processing: iteration={i}, _data_len={}, sample={:?}",
-                    tcp_reassembler._data.len(),
-                    sample
-                );
-            }
-            let packet_size = match BinaryReader::from(tcp_reassembler._data.clone()).read_u32() {
-                Ok(sz) => sz,
-                Err(e) => {
-                    debug!("Malformed reassembled packet: failed to read_u32: {e}");
-                    break;
-                }
-            };
-            if tcp_reassembler._data.len() < packet_size as usize {
-                break;
-            }
-            if tcp_reassembler._data.len() >= packet_size as usize {
-                let (left, right) = tcp_reassembler._data.split_at(packet_size as usize);
-                let packet = left.to_vec();
-                tcp_reassembler._data = right.to_vec();
-                debug!(
-                    "Processing packet at line {}: size={}",
-                    line!(),
-                    packet_size
-                );
-                process_packet(BinaryReader::from(packet), packet_sender.clone()).await;
-            }
+        encounter
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn summary(encounter: &Encounter) -> (Option<u32>, Vec<(i64, Option<i64>, Option<LeftViewReason>, bool)>, Vec<HpReport>) {
+        let tracked_bosses = encounter
+            .tracked_bosses
+            .iter()
+            .map(|(uid, tracked_boss)| {
+                (*uid, tracked_boss.curr_hp(), tracked_boss.left_view, tracked_boss.death_report_in_flight)
+            })
+            .collect();
+        (encounter.local_player.line_id, tracked_bosses, encounter.pending_reports.clone())
+    }
+
+    #[test]
+    fn test_finished_replay_is_not_restarted() {
+        let pcap = CaptureBackend::Pcap { device: None };
+        let replay = CaptureBackend::Replay {
+            path: "session.pcapng".into(),
+            timing: ReplayTiming::AsFastAsPossible,
+        };
+        assert_eq!(stall_timeout_from_vars(&pcap, |_| None), Some(DEFAULT_STALL_TIMEOUT));
+        assert_eq!(stall_timeout_from_vars(&pcap, |_| Some("30".to_string())), Some(Duration::from_secs(30)));
+        assert_eq!(stall_timeout_from_vars(&pcap, |_| Some("0".to_string())), None);
+        // the watchdog isn't started for a replay, even when asked for
+        assert_eq!(stall_timeout_from_vars(&replay, |_| None), None);
+        assert_eq!(stall_timeout_from_vars(&replay, |_| Some("30".to_string())), None);
+    }
+
+    #[tokio::test]
+    async fn test_recorded_session_replays_into_the_same_encounter() {
+        let messages = boss_fight();
+        let stream: Vec<u8> = messages
+            .iter()
+            .flat_map(|(op, message)| notify(*op, message))
+            .collect();
+
+        // record the session, the stream split across segments the way TCP delivers it
+        let directory = std::env::temp_dir().join(format!("bptimer-replay-{}", std::process::id()));
+        let server = Server::new(SERVER_ADDR, SERVER_PORT, CLIENT_ADDR, CLIENT_PORT);
+        let mut recorder = SessionRecorder::default();
+        recorder.configure(directory.clone(), "test".to_string());
+        let login_return = login_return();
+        recorder.on_server_detected(server, &tcp_packet(1000, &login_return));
+        recorder.set_enabled(true).unwrap();
+        let stream_seq = 1000 + login_return.len() as u32;
+        for (i, segment) in stream.chunks(100).enumerate() {
+            recorder.record(server, &tcp_packet(stream_seq + (i * 100) as u32, segment));
         }
-        if *restart_receiver.borrow() {
-            break;
+        recorder.set_enabled(false).unwrap();
+        let path = fs::read_dir(&directory).unwrap().next().unwrap().unwrap().path();
+
+        // replay it through the capture pipeline
+        let source = ReplaySource::open(&path, ReplayTiming::AsFastAsPossible).unwrap();
+        let mut source_packets = spawn_source_reader(Box::new(source));
+        let (packet_sender, mut packet_receiver) = mpsc::channel(64);
+        let (_restart_sender, mut restart_receiver) = watch::channel(false);
+        read_packets(&mut source_packets, &packet_sender, &mut restart_receiver, &mut None).await;
+        drop(packet_sender);
+        fs::remove_dir_all(&directory).unwrap();
+        let mut replayed = Vec::new();
+        while let Some((direction, op, data)) = packet_receiver.recv().await {
+            assert_eq!(direction, StreamDirection::Downstream);
+            replayed.push((op, data));
         }
+
+        let mut expected = vec![(Pkt::ServerChangeInfo, Bytes::new())];
+        expected.extend(messages.into_iter().map(|(op, message)| (op, Bytes::from(message))));
+        assert_eq!(replayed, expected);
+
+        let replayed = apply(replayed);
+        assert_eq!(summary(&replayed), summary(&apply(expected)));
+        let (line_id, tracked_bosses, reports) = summary(&replayed);
+        assert_eq!(line_id, Some(3));
+        assert_eq!(tracked_bosses, vec![(BOSS_UID, Some(0), Some(LeftViewReason::Dead), true)]);
+        let report = |hp_pct, (pos_x, pos_y)| HpReport {
+            uid: Some(BOSS_UID),
+            monster_id: BOSS_ID,
+            hp_pct,
+            line: 3,
+            pos_x,
+            pos_y,
+        };
+        // where the local player stands until the boss is hit, then where the hits land
+        assert_eq!(reports, vec![report(90, (10.0, 20.0)), report(40, (30.0, 40.0)), report(0, (30.0, 40.0))]);
     }
 }
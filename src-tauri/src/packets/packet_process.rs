@@ -1,147 +1,409 @@
 use crate::packets;
-use crate::packets::opcodes::{FragmentType, Pkt};
-use crate::packets::utils::BinaryReader;
-use log::debug;
+use crate::packets::capture_stats::CAPTURE_STATS;
+use crate::packets::opcode_discovery::OPCODE_DISCOVERY;
+use crate::packets::opcodes::{FragmentType, FrameError, Pkt, RpcCall};
+use crate::packets::utils::{BinaryReader, FRAME_HEADER_SIZE, StreamDirection};
+use bytes::Bytes;
+use log::{debug, warn};
+use std::collections::{HashMap, VecDeque};
+use std::io::Read;
 
-pub async fn process_packet(
-    mut packets_reader: BinaryReader,
-    packet_sender: tokio::sync::mpsc::Sender<(packets::opcodes::Pkt, Vec<u8>)>,
-) {
-    while packets_reader.remaining() > 0 {
-        let packet_size = match packets_reader.peek_u32() {
-            Ok(sz) => sz,
-            Err(e) => {
-                debug!("Malformed packet: failed to peek_u32: {e}");
-                continue;
-            }
-        };
-        if packet_size < 6 {
-            debug!("Malformed packet: packet_size < 6");
-            continue;
+const MAX_PENDING_CALLS: usize = 1024;
+
+/// Calls still waiting for their `Return`, keyed by stub id.
+#[derive(Default)]
+pub struct PendingCalls {
+    calls: HashMap<u32, RpcCall>,
+    order: VecDeque<u32>,
+}
+
+impl PendingCalls {
+    fn insert(&mut self, call: RpcCall) {
+        if self.calls.insert(call.stub_id, call).is_none() {
+            self.order.push_back(call.stub_id);
+        }
+        if self.order.len() > 2 * MAX_PENDING_CALLS {
+            self.order.retain(|stub_id| self.calls.contains_key(stub_id));
+        }
+        // calls that never got a return are forgotten oldest first
+        while self.calls.len() > MAX_PENDING_CALLS {
+            let Some(stub_id) = self.order.pop_front() else {
+                break;
+            };
+            self.calls.remove(&stub_id);
         }
+    }
 
-        let mut reader = match packets_reader.read_bytes(packet_size as usize) {
-            Ok(bytes) => BinaryReader::from(bytes),
-            Err(e) => {
-                debug!("Malformed packet: failed to read_bytes: {e}");
-                continue;
-            }
-        };
-        if reader.read_u32().is_err() {
-            debug!("Malformed packet: failed to skip u32");
-            continue;
+    fn take(&mut self, stub_id: u32) -> Option<RpcCall> {
+        self.calls.remove(&stub_id)
+    }
+
+    pub fn clear(&mut self) {
+        self.calls.clear();
+        self.order.clear();
+    }
+}
+
+/// Nested `FrameDown`/`FrameUp` levels below the outer frame, the game itself only nests once.
+const MAX_FRAME_DEPTH: usize = 4;
+const MAX_DECOMPRESSED_SIZE: usize = 32 * 1024 * 1024;
+const SERVICE_UUID: u64 = 0x0000000063335342;
+
+fn read_payload(reader: &mut BinaryReader, is_zstd_compressed: bool) -> Result<Bytes, FrameError> {
+    let payload = reader.read_remaining();
+    if !is_zstd_compressed {
+        return Ok(payload);
+    }
+    decompress(&payload)
+}
+
+/// Decompresses a zstd payload, refusing to inflate it beyond `MAX_DECOMPRESSED_SIZE`.
+fn decompress(payload: &[u8]) -> Result<Bytes, FrameError> {
+    let decoder =
+        zstd::stream::read::Decoder::new(payload).map_err(|e| FrameError::Decompress(e.to_string()))?;
+    let mut decompressed = Vec::new();
+    decoder
+        .take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| FrameError::Decompress(e.to_string()))?;
+    if decompressed.len() > MAX_DECOMPRESSED_SIZE {
+        return Err(FrameError::DecompressedTooLarge {
+            limit: MAX_DECOMPRESSED_SIZE,
+        });
+    }
+    Ok(Bytes::from(decompressed))
+}
+
+/// Splits the next size-prefixed frame off `reader`.
+/// Without a plausible size the next frame boundary is unknown, so the rest is dropped.
+fn split_frame(reader: &mut BinaryReader) -> Result<BinaryReader, FrameError> {
+    let remaining = reader.remaining();
+    let size = match reader.peek_u32() {
+        Ok(size) => size,
+        Err(_) => {
+            reader.read_remaining();
+            return Err(FrameError::Truncated);
         }
-        let packet_type = match reader.read_u16() {
-            Ok(pt) => pt,
-            Err(e) => {
-                debug!("Malformed packet: failed to read_u16: {e}");
-                continue;
-            }
-        };
-        let is_zstd_compressed = packet_type & 0x8000;
+    };
+    if (size as usize) < FRAME_HEADER_SIZE || size as usize > remaining {
+        reader.read_remaining();
+        return Err(FrameError::InvalidSize { size, remaining });
+    }
+    Ok(BinaryReader::from(reader.read_bytes(size as usize)?))
+}
+
+/// Decodes the game messages of a reassembled frame, including nested frames.
+///
+/// Every call to `next` consumes input, so malformed data ends the iteration instead of
+/// stalling it. A malformed frame yields an error and decoding continues with the next one.
+pub struct FrameDecoder<'a> {
+    // innermost nested frame last
+    readers: Vec<BinaryReader>,
+    pending_calls: &'a mut PendingCalls,
+}
+
+impl<'a> FrameDecoder<'a> {
+    pub fn new(packet: Bytes, pending_calls: &'a mut PendingCalls) -> Self {
+        Self {
+            readers: vec![BinaryReader::from(packet)],
+            pending_calls,
+        }
+    }
+
+    /// Decodes one frame. `Ok(None)` for frames that don't carry a message.
+    fn decode_frame(
+        &mut self,
+        mut reader: BinaryReader,
+        depth: usize,
+    ) -> Result<Option<(Pkt, Bytes)>, FrameError> {
+        let _size = reader.read_u32()?;
+        let packet_type = reader.read_u16()?;
+        let is_zstd_compressed = packet_type & 0x8000 != 0;
         let msg_type_id = packet_type & 0x7fff;
 
-        match packets::opcodes::FragmentType::from(msg_type_id) {
+        let fragment_type = FragmentType::from(msg_type_id);
+        match fragment_type {
             FragmentType::Notify => {
-                let service_uuid = match reader.read_u64() {
-                    Ok(su) => su,
-                    Err(e) => {
-                        debug!("Malformed Notify: failed to read_u64 service_uuid: {e}");
-                        continue;
-                    }
-                };
-                let _stub_id = match reader.read_u32() {
-                    Ok(sid) => sid,
-                    Err(e) => {
-                        debug!("Malformed Notify: failed to read_u32 stub_id: {e}");
-                        continue;
-                    }
+                let service_uuid = reader.read_u64()?;
+                let _stub_id = reader.read_u32()?;
+                let method_id = reader.read_u32()?;
+                if service_uuid != SERVICE_UUID {
+                    return Err(FrameError::ServiceMismatch(service_uuid));
+                }
+                let Ok(pkt) = Pkt::try_from(method_id) else {
+                    return Err(FrameError::UnknownMethod {
+                        method_id,
+                        payload: reader.read_remaining(),
+                        is_zstd_compressed,
+                    });
                 };
-                let method_id_raw = match reader.read_u32() {
-                    Ok(mid) => mid,
-                    Err(e) => {
-                        debug!("Malformed Notify: failed to read_u32 method_id: {e}");
-                        continue;
-                    }
+                let msg_payload = read_payload(&mut reader, is_zstd_compressed)?;
+                Ok(Some((pkt, msg_payload)))
+            }
+            FragmentType::Call => {
+                let call = RpcCall {
+                    service_uuid: reader.read_u64()?,
+                    stub_id: reader.read_u32()?,
+                    method_id: reader.read_u32()?,
                 };
-
-                if service_uuid != 0x0000000063335342 {
-                    debug!("Notify: service_uuid mismatch: {service_uuid:x}");
-                    continue;
+                let msg_payload = read_payload(&mut reader, is_zstd_compressed)?;
+                self.pending_calls.insert(call);
+                Ok(Some((Pkt::Call(call), msg_payload)))
+            }
+            FragmentType::Return => {
+                let stub_id = reader.read_u32()?;
+                let msg_payload = read_payload(&mut reader, is_zstd_compressed)?;
+                let call = self.pending_calls.take(stub_id);
+                if call.is_none() {
+                    debug!("Return: no pending call for stub_id {stub_id}");
                 }
-
-                let msg_payload = reader.read_remaining();
-                let mut tcp_fragment_vec = msg_payload.to_vec();
-                if is_zstd_compressed != 0 {
-                    match zstd::decode_all(tcp_fragment_vec.as_slice()) {
-                        Ok(decoded) => tcp_fragment_vec = decoded,
-                        Err(e) => {
-                            debug!("Notify: zstd decompression failed: {e}");
-                            continue;
-                        }
-                    }
+                Ok(Some((Pkt::Return { stub_id, call }, msg_payload)))
+            }
+            FragmentType::Echo => {
+                debug!("Echo: keepalive, {} bytes", reader.remaining());
+                Ok(None)
+            }
+            FragmentType::FrameDown | FragmentType::FrameUp => {
+                let _sequence_id = reader.read_u32()?;
+                if depth + 1 > MAX_FRAME_DEPTH {
+                    return Err(FrameError::TooDeep {
+                        depth: MAX_FRAME_DEPTH,
+                    });
                 }
+                let nested_packet = read_payload(&mut reader, is_zstd_compressed)?;
+                self.readers.push(BinaryReader::from(nested_packet));
+                Ok(None)
+            }
+            FragmentType::None => Err(FrameError::UnknownFragment(msg_type_id)),
+        }
+    }
+}
 
-                let method_id = match Pkt::try_from(method_id_raw) {
-                    Ok(mid) => mid,
-                    Err(_) => {
-                        debug!("Notify: Skipping unknown methodId: {method_id_raw}");
-                        continue;
-                    }
-                };
+impl Iterator for FrameDecoder<'_> {
+    type Item = Result<(Pkt, Bytes), FrameError>;
 
-                if let Err(err) = packet_sender.send((method_id, tcp_fragment_vec)).await {
-                    debug!("Failed to send packet: {err}");
-                }
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // the outer frame is at depth 0
+            let depth = self.readers.len().saturating_sub(1);
+            let reader = self.readers.last_mut()?;
+            if reader.remaining() == 0 {
+                self.readers.pop();
+                continue;
             }
-            FragmentType::FrameDown => {
-                let _server_sequence_id = match reader.read_u32() {
-                    Ok(sid) => sid,
-                    Err(e) => {
-                        debug!("FrameDown: failed to read_u32 server_sequence_id: {e}");
-                        continue;
-                    }
-                };
-                if reader.remaining() == 0 {
-                    debug!("FrameDown: reader.remaining() == 0");
-                    break;
-                }
+            let decoded = split_frame(reader).and_then(|frame| self.decode_frame(frame, depth));
+            match decoded {
+                Ok(Some(message)) => return Some(Ok(message)),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
 
-                let nested_packet = reader.read_remaining();
-                if is_zstd_compressed != 0 {
-                    match zstd::decode_all(nested_packet) {
-                        Ok(tcp_fragment_decompressed) => {
-                            packets_reader = BinaryReader::from(tcp_fragment_decompressed);
-                        }
-                        Err(e) => {
-                            debug!("FrameDown: zstd decompression failed: {e}");
-                            continue;
-                        }
-                    }
-                } else {
-                    packets_reader = BinaryReader::from(Vec::from(nested_packet));
-                }
+/// Decodes every game message of a reassembled frame, in stream order.
+/// Malformed frames are logged, counted and skipped.
+pub fn decode_packet(packet: Bytes, pending_calls: &mut PendingCalls) -> Vec<(Pkt, Bytes)> {
+    let mut malformed = false;
+    let messages = FrameDecoder::new(packet, pending_calls)
+        .filter_map(|decoded| {
+            decoded
+                .inspect_err(|e| {
+                    // a message of an unknown method is still a well-formed frame
+                    malformed |= !matches!(e, FrameError::UnknownMethod { .. });
+                    on_frame_error(e);
+                })
+                .ok()
+        })
+        .collect();
+    let mut stats = CAPTURE_STATS.lock().unwrap();
+    if malformed {
+        stats.frame_failures += 1;
+    } else {
+        stats.record_frame();
+    }
+    messages
+}
+
+fn on_frame_error(e: &FrameError) {
+    debug!("Skipping frame: {e}");
+    match e {
+        FrameError::Decompress(_) | FrameError::DecompressedTooLarge { .. } => {
+            CAPTURE_STATS.lock().unwrap().zstd_failures += 1;
+        }
+        FrameError::UnknownMethod {
+            method_id,
+            payload,
+            is_zstd_compressed,
+        } => {
+            let mut discovery = OPCODE_DISCOVERY.lock().unwrap();
+            // unknown payloads are only decompressed when someone collects them
+            if !discovery.is_enabled() {
+                return;
             }
-            _ => {
-                debug!("Unknown fragment type: {msg_type_id}");
-                continue;
+            let payload = if *is_zstd_compressed {
+                decompress(payload)
+            } else {
+                Ok(payload.clone())
+            };
+            match payload {
+                Ok(payload) => discovery.record(*method_id, &payload),
+                Err(e) => warn!("Failed to decompress unknown method {method_id:#x}: {e}"),
             }
         }
+        _ => {}
+    }
+}
+
+/// Decodes a reassembled frame and forwards its messages to the live pipeline, tagged with
+/// the direction of the stream they were read from.
+pub async fn process_packet(
+    packet: Bytes,
+    direction: StreamDirection,
+    packet_sender: &tokio::sync::mpsc::Sender<(StreamDirection, packets::opcodes::Pkt, Bytes)>,
+    pending_calls: &mut PendingCalls,
+) {
+    for (pkt, data) in decode_packet(packet, pending_calls) {
+        if let Err(err) = packet_sender.send((direction, pkt, data)).await {
+            debug!("Failed to send packet: {err}");
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::packets::opcodes::Pkt;
-    use crate::packets::packet_process::process_packet;
-    use crate::packets::utils::BinaryReader;
+    use crate::live::opcodes_models::Encounter;
+    use crate::live::opcodes_process::process_notify;
+    use crate::packets::opcodes::{FrameError, Pkt};
+    use crate::packets::packet_process::{
+        decode_packet, FrameDecoder, PendingCalls, MAX_DECOMPRESSED_SIZE, MAX_FRAME_DEPTH,
+        SERVICE_UUID,
+    };
+    use bytes::Bytes;
+    use proptest::prelude::*;
 
-    #[tokio::test]
-    async fn test_add() {
+    fn frame(packet_type: u16, body: &[u8]) -> Vec<u8> {
+        let mut frame = ((body.len() + 6) as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&packet_type.to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    fn notify(method_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut body = SERVICE_UUID.to_be_bytes().to_vec();
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend_from_slice(&method_id.to_be_bytes());
+        body.extend_from_slice(payload);
+        frame(2, &body)
+    }
+
+    fn frame_down(nested: &[u8]) -> Vec<u8> {
+        let mut body = 1u32.to_be_bytes().to_vec();
+        body.extend_from_slice(nested);
+        frame(6, &body)
+    }
+
+    fn decode(packet: Vec<u8>) -> Vec<Result<(Pkt, Bytes), FrameError>> {
+        FrameDecoder::new(Bytes::from(packet), &mut PendingCalls::default()).collect()
+    }
+
+    #[test]
+    fn test_decoder_bounds_nesting_depth() {
+        let mut packet = notify(0x2b, b"time");
+        for _ in 0..MAX_FRAME_DEPTH {
+            packet = frame_down(&packet);
+        }
+        assert_eq!(
+            decode(packet.clone()),
+            vec![Ok((Pkt::SyncServerTime, Bytes::from_static(b"time")))]
+        );
+        packet = frame_down(&packet);
+        assert_eq!(decode(packet), vec![Err(FrameError::TooDeep { depth: MAX_FRAME_DEPTH })]);
+    }
+
+    #[test]
+    fn test_decoder_checks_method_before_decompressing() {
+        // not valid zstd, an unknown method is rejected without touching its payload
+        let mut packet = notify(0xfff, b"garbage");
+        packet[4] |= 0x80;
+        assert_eq!(
+            decode(packet),
+            vec![Err(FrameError::UnknownMethod {
+                method_id: 0xfff,
+                payload: Bytes::from_static(b"garbage"),
+                is_zstd_compressed: true,
+            })]
+        );
+    }
+
+    #[test]
+    fn test_decoder_bounds_decompressed_size() {
+        let bomb = zstd::encode_all(&vec![0u8; MAX_DECOMPRESSED_SIZE + 1][..], 3).unwrap();
+        let mut body = 1u32.to_be_bytes().to_vec();
+        body.extend_from_slice(&bomb);
+        assert_eq!(
+            decode(frame(0x8006, &body)),
+            vec![Err(FrameError::DecompressedTooLarge { limit: MAX_DECOMPRESSED_SIZE })]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_decoder_terminates_on_random_input(packet in proptest::collection::vec(any::<u8>(), 0..512)) {
+            // every item consumes at least one byte of input
+            let len = packet.len();
+            prop_assert!(decode(packet).len() <= len);
+        }
+
+        #[test]
+        fn test_decoder_recovers_after_malformed_frames(
+            garbage in proptest::collection::vec(any::<u8>(), 0..64),
+            nesting in 0..=MAX_FRAME_DEPTH,
+        ) {
+            let mut valid = notify(0x2b, b"time");
+            for _ in 0..nesting {
+                valid = frame_down(&valid);
+            }
+            // a malformed but correctly sized frame in front doesn't hide the next one
+            let mut packet = frame(0, &garbage);
+            packet.extend_from_slice(&valid);
+            let decoded = decode(packet);
+            prop_assert_eq!(decoded.len(), 2);
+            prop_assert_eq!(
+                decoded[1].clone(),
+                Ok((Pkt::SyncServerTime, Bytes::from_static(b"time")))
+            );
+        }
+    }
+
+    #[test]
+    fn test_add() {
         use std::fs;
-        let (packet_sender, _) = tokio::sync::mpsc::channel::<(Pkt, Vec<u8>)>(1);
         let filename = "src/packets/test_add_packet.json";
         let v: Vec<u8> = serde_json::from_str(&fs::read_to_string(filename).expect(&format!("Failed to open {filename}"))).expect("Invalid JSON in test_packet.json");
-        process_packet(BinaryReader::from(v), packet_sender).await;
+        let messages = decode_packet(Bytes::from(v), &mut PendingCalls::default());
+        let summary: Vec<(Pkt, usize)> =
+            messages.iter().map(|(pkt, payload)| (*pkt, payload.len())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Pkt::SyncServerTime, 7),
+                (Pkt::SyncContainerData, 36803),
+                (Pkt::SyncContainerDirtyData, 28),
+                (Pkt::SyncContainerDirtyData, 28),
+                (Pkt::SyncContainerDirtyData, 28),
+                (Pkt::SyncDungeonData, 75),
+            ]
+        );
+
+        // the dirty data has no changed fields and leaves the local player as synced
+        let mut encounter = Encounter::default();
+        for (pkt, payload) in messages {
+            process_notify(&mut encounter, pkt, payload).unwrap();
+        }
+        let local_player = encounter.local_player;
+        assert_eq!((local_player.map_id, local_player.line_id), (Some(7), Some(2)));
+        let pos = local_player.pos.unwrap();
+        assert_eq!((pos.x, pos.y, pos.z), (78.62847, 137.43805, -337.0174));
     }
 }
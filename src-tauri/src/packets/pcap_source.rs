@@ -0,0 +1,64 @@
+use crate::packets::capture_source::{CaptureSource, NextPacket, ip_payload};
+use log::{error, info};
+use pcap::{Active, Capture, Device};
+
+pub struct PcapSource {
+    capture: Capture<Active>,
+    link_type: u32,
+    buffer: Vec<u8>,
+}
+
+impl PcapSource {
+    /// Opens a live capture on `device`. Without a device this listens on `any` on Linux
+    /// and on libpcap's default device elsewhere.
+    pub fn open(device: Option<&str>) -> Result<Self, String> {
+        let device = match device {
+            Some(name) => Device::from(name),
+            None if cfg!(target_os = "linux") => Device::from("any"),
+            None => Device::lookup()
+                .map_err(|e| format!("Failed to look up pcap device: {e}"))?
+                .ok_or_else(|| "No pcap capture device available".to_string())?,
+        };
+        let device_name = device.name.clone();
+        let mut capture = Capture::from_device(device)
+            .and_then(|c| c.snaplen(65535).timeout(1000).immediate_mode(true).open())
+            .map_err(|e| format!("Failed to open pcap capture on {device_name}: {e}"))?;
+        capture
+            .filter("ip and tcp", true)
+            .map_err(|e| format!("Failed to set pcap filter: {e}"))?;
+        let link_type = capture.get_datalink().0 as u32;
+        info!("pcap capture opened on {device_name} (link type {link_type})");
+        Ok(Self {
+            capture,
+            link_type,
+            buffer: Vec::with_capacity(65535),
+        })
+    }
+}
+
+impl CaptureSource for PcapSource {
+    fn name(&self) -> &'static str {
+        "pcap"
+    }
+
+    fn next_packet(&mut self) -> NextPacket<'_> {
+        loop {
+            match self.capture.next_packet() {
+                Ok(packet) => {
+                    let Some(ip_packet) = ip_payload(self.link_type, packet.data) else {
+                        continue;
+                    };
+                    self.buffer.clear();
+                    self.buffer.extend_from_slice(ip_packet);
+                    break;
+                }
+                Err(pcap::Error::TimeoutExpired) => return NextPacket::Idle,
+                Err(e) => {
+                    error!("pcap recv failed: {e}");
+                    return NextPacket::Closed;
+                }
+            }
+        }
+        NextPacket::Packet(&self.buffer)
+    }
+}
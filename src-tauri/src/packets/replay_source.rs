@@ -0,0 +1,150 @@
+use crate::packets::capture_source::{CaptureSource, NextPacket, ip_payload};
+use log::{error, info};
+use pcap_file::pcap::PcapReader;
+use pcap_file::pcapng::{Block, PcapNgReader};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayTiming {
+    /// Sleep between packets to reproduce the original capture timing.
+    Original,
+    /// Feed packets as fast as the pipeline accepts them.
+    AsFastAsPossible,
+}
+
+enum ReplayReader {
+    Pcap(PcapReader<BufReader<File>>),
+    PcapNg(PcapNgReader<BufReader<File>>),
+}
+
+/// Replays a recorded pcap/pcapng file through the capture pipeline.
+pub struct ReplaySource {
+    reader: ReplayReader,
+    timing: ReplayTiming,
+    buffer: Vec<u8>,
+    first_timestamp: Option<Duration>,
+    last_timestamp: Duration,
+    started_at: Instant,
+    packet_count: u64,
+}
+
+impl ReplaySource {
+    pub fn open(path: &Path, timing: ReplayTiming) -> Result<Self, String> {
+        const PCAPNG_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+
+        let mut file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)
+            .and_then(|()| file.seek(SeekFrom::Start(0)))
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+
+        let file = BufReader::new(file);
+        let reader = if magic == PCAPNG_MAGIC {
+            ReplayReader::PcapNg(
+                PcapNgReader::new(file).map_err(|e| format!("Invalid pcapng file: {e}"))?,
+            )
+        } else {
+            ReplayReader::Pcap(PcapReader::new(file).map_err(|e| format!("Invalid pcap file: {e}"))?)
+        };
+        info!("Replaying {} ({timing:?})", path.display());
+        Ok(Self {
+            reader,
+            timing,
+            buffer: Vec::with_capacity(65535),
+            first_timestamp: None,
+            last_timestamp: Duration::ZERO,
+            started_at: Instant::now(),
+            packet_count: 0,
+        })
+    }
+
+    /// Reads the next captured frame into `buffer` and returns its link type.
+    fn read_frame(&mut self) -> Option<u32> {
+        loop {
+            match &mut self.reader {
+                ReplayReader::Pcap(reader) => {
+                    let link_type = u32::from(reader.header().datalink);
+                    match reader.next_packet()? {
+                        Ok(packet) => {
+                            self.buffer.clear();
+                            self.buffer.extend_from_slice(&packet.data);
+                            self.last_timestamp = packet.timestamp;
+                            return Some(link_type);
+                        }
+                        Err(e) => {
+                            error!("Failed to read pcap packet: {e}");
+                            return None;
+                        }
+                    }
+                }
+                ReplayReader::PcapNg(reader) => {
+                    let interface_id = match reader.next_block()? {
+                        Ok(Block::EnhancedPacket(packet)) => {
+                            self.buffer.clear();
+                            self.buffer.extend_from_slice(&packet.data);
+                            self.last_timestamp = packet.timestamp;
+                            packet.interface_id
+                        }
+                        Ok(Block::SimplePacket(packet)) => {
+                            self.buffer.clear();
+                            self.buffer.extend_from_slice(&packet.data);
+                            0
+                        }
+                        Ok(_) => continue,
+                        Err(e) => {
+                            error!("Failed to read pcapng block: {e}");
+                            return None;
+                        }
+                    };
+                    let Some(interface) = reader.interfaces().get(interface_id as usize) else {
+                        error!("pcapng packet references unknown interface {interface_id}");
+                        return None;
+                    };
+                    return Some(u32::from(interface.linktype));
+                }
+            }
+        }
+    }
+
+    fn wait_for_original_timing(&mut self) {
+        let first_timestamp = *self.first_timestamp.get_or_insert_with(|| {
+            self.started_at = Instant::now();
+            self.last_timestamp
+        });
+        let offset = self.last_timestamp.saturating_sub(first_timestamp);
+        let elapsed = self.started_at.elapsed();
+        if offset > elapsed {
+            std::thread::sleep(offset - elapsed);
+        }
+    }
+}
+
+impl CaptureSource for ReplaySource {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn next_packet(&mut self) -> NextPacket<'_> {
+        let link_type = loop {
+            let Some(link_type) = self.read_frame() else {
+                info!("Replay finished after {} packets", self.packet_count);
+                return NextPacket::Closed;
+            };
+            if ip_payload(link_type, &self.buffer).is_some() {
+                break link_type;
+            }
+        };
+        self.packet_count += 1;
+        if self.timing == ReplayTiming::Original {
+            self.wait_for_original_timing();
+        }
+        match ip_payload(link_type, &self.buffer) {
+            Some(packet) => NextPacket::Packet(packet),
+            None => NextPacket::Closed,
+        }
+    }
+}
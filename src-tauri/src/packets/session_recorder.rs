@@ -0,0 +1,225 @@
+use crate::packets::utils::{Server, remove_old_files};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use pcap_file::DataLink;
+use pcap_file::pcapng::PcapNgWriter;
+use pcap_file::pcapng::blocks::enhanced_packet::EnhancedPacketBlock;
+use pcap_file::pcapng::blocks::interface_description::InterfaceDescriptionBlock;
+use pcap_file::pcapng::blocks::section_header::{SectionHeaderBlock, SectionHeaderOption};
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024; // 64 mb
+const MAX_FILES: usize = 10;
+const FILE_PREFIX: &str = "session-";
+const FILE_EXTENSION: &str = "pcapng";
+
+pub static SESSION_RECORDER: Lazy<Mutex<SessionRecorder>> =
+    Lazy::new(|| Mutex::new(SessionRecorder::default()));
+
+struct Recording {
+    writer: PcapNgWriter<BufWriter<File>>,
+    server: Server,
+    path: PathBuf,
+    bytes_written: u64,
+}
+
+/// Writes the game traffic seen by the capture pipeline to rotating pcapng files,
+/// which can be replayed later with `BPTIMER_REPLAY_FILE`.
+#[derive(Default)]
+pub struct SessionRecorder {
+    directory: Option<PathBuf>,
+    app_version: String,
+    enabled: bool,
+    recording: Option<Recording>,
+    /// The packet the game server was detected from, written at the start of every file so
+    /// each file can be replayed on its own.
+    detection_packet: Option<(Server, Vec<u8>)>,
+}
+
+impl SessionRecorder {
+    pub fn configure(&mut self, directory: PathBuf, app_version: String) {
+        self.directory = Some(directory);
+        self.app_version = app_version;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<(), String> {
+        if enabled && self.directory.is_none() {
+            return Err("Session recorder has no output directory".to_string());
+        }
+        if !enabled {
+            self.finish();
+        }
+        self.enabled = enabled;
+        info!("Session recording {}", if enabled { "enabled" } else { "disabled" });
+        Ok(())
+    }
+
+    /// Keeps the packet `server` was detected from and starts a new file with it.
+    pub fn on_server_detected(&mut self, server: Server, packet: &[u8]) {
+        self.detection_packet = Some((server, packet.to_vec()));
+        if self.enabled {
+            self.rotate(server);
+        }
+    }
+
+    /// Appends an IP packet belonging to `server`'s flow to the current recording.
+    pub fn record(&mut self, server: Server, packet: &[u8]) {
+        if !self.enabled {
+            return;
+        }
+        let needs_new_file = self.recording.as_ref().is_none_or(|recording| {
+            recording.server != server || recording.bytes_written >= MAX_FILE_SIZE
+        });
+        if needs_new_file {
+            self.rotate(server);
+        }
+        let Some(recording) = self.recording.as_mut() else {
+            return;
+        };
+
+        match recording.writer.write_pcapng_block(packet_block(packet)) {
+            Ok(written) => recording.bytes_written += written as u64,
+            Err(e) => {
+                warn!("Failed to write to {}: {e}", recording.path.display());
+                self.finish();
+            }
+        }
+    }
+
+    fn rotate(&mut self, server: Server) {
+        self.finish();
+        match self.start(server) {
+            Ok(recording) => self.recording = Some(recording),
+            Err(e) => {
+                warn!("Failed to start session recording, disabling it: {e}");
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn start(&self, server: Server) -> Result<Recording, String> {
+        let directory = self
+            .directory
+            .as_ref()
+            .ok_or_else(|| "Session recorder has no output directory".to_string())?;
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create {}: {e}", directory.display()))?;
+        remove_old_files(directory, FILE_PREFIX, FILE_EXTENSION, MAX_FILES);
+
+        let file_name = format!(
+            "{FILE_PREFIX}{}.{FILE_EXTENSION}",
+            chrono::Local::now().format("%Y-%m-%d_%H-%M-%S%.3f")
+        );
+        let path = directory.join(file_name);
+        let file = File::create(&path)
+            .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+
+        let section = SectionHeaderBlock {
+            options: vec![
+                SectionHeaderOption::Comment(Cow::Owned(format!(
+                    "bptimer-auto v{}",
+                    self.app_version
+                ))),
+                SectionHeaderOption::Comment(Cow::Owned(format!("server: {server}"))),
+            ],
+            ..Default::default()
+        };
+        let mut writer = PcapNgWriter::with_section_header(BufWriter::new(file), section)
+            .map_err(|e| format!("Failed to write pcapng header: {e}"))?;
+        let interface = InterfaceDescriptionBlock {
+            linktype: DataLink::RAW,
+            snaplen: 0,
+            options: vec![],
+        };
+        let mut bytes_written = writer
+            .write_pcapng_block(interface)
+            .map_err(|e| format!("Failed to write pcapng interface: {e}"))?
+            as u64;
+        if let Some((_, packet)) = self
+            .detection_packet
+            .as_ref()
+            .filter(|(detected_server, _)| *detected_server == server)
+        {
+            bytes_written += writer
+                .write_pcapng_block(packet_block(packet))
+                .map_err(|e| format!("Failed to write the server detection packet: {e}"))?
+                as u64;
+        }
+
+        info!("Recording session for {server} to {}", path.display());
+        Ok(Recording {
+            writer,
+            server,
+            path,
+            bytes_written,
+        })
+    }
+
+    fn finish(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+        if let Err(e) = recording.writer.into_inner().into_inner() {
+            warn!("Failed to flush {}: {e}", recording.path.display());
+        }
+        info!(
+            "Finished recording {} ({} bytes)",
+            recording.path.display(),
+            recording.bytes_written
+        );
+    }
+}
+
+fn packet_block(packet: &[u8]) -> EnhancedPacketBlock<'_> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    EnhancedPacketBlock {
+        interface_id: 0,
+        timestamp,
+        original_len: packet.len() as u32,
+        data: Cow::Borrowed(packet),
+        options: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packets::session_recorder::SessionRecorder;
+    use crate::packets::utils::Server;
+    use pcap_file::pcapng::{Block, PcapNgReader};
+    use std::fs::{self, File};
+
+    #[test]
+    fn test_recordings_start_with_the_detection_packet() {
+        let directory = std::env::temp_dir().join(format!("bptimer-recorder-{}", std::process::id()));
+        let server = Server::new([10, 0, 0, 1], 5003, [192, 168, 0, 2], 50000);
+        let mut recorder = SessionRecorder::default();
+        recorder.configure(directory.clone(), "test".to_string());
+        // detected before recording was turned on
+        recorder.on_server_detected(server, b"login");
+        recorder.set_enabled(true).unwrap();
+        recorder.record(server, b"notify");
+        recorder.set_enabled(false).unwrap();
+
+        let path = fs::read_dir(&directory).unwrap().next().unwrap().unwrap().path();
+        let mut reader = PcapNgReader::new(File::open(&path).unwrap()).unwrap();
+        let mut packets = Vec::new();
+        while let Some(block) = reader.next_block() {
+            if let Block::EnhancedPacket(packet) = block.unwrap() {
+                packets.push(packet.data.into_owned());
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(packets, vec![b"login".to_vec(), b"notify".to_vec()]);
+    }
+}
@@ -1,6 +1,10 @@
-use byteorder::{BigEndian, ReadBytesExt};
+use crate::packets::opcodes::FragmentType;
+use bytes::{Buf, Bytes, BytesMut};
+use log::{info, warn};
 use std::collections::BTreeMap;
-use std::io::{Cursor, Read};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use std::{fmt, io};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -20,6 +24,11 @@ impl Server {
             dst_port,
         }
     }
+
+    /// The same connection seen from the other direction.
+    pub fn reversed(&self) -> Self {
+        Self::new(self.dst_addr, self.dst_port, self.src_addr, self.src_port)
+    }
 }
 
 impl fmt::Display for Server {
@@ -35,93 +44,406 @@ impl fmt::Display for Server {
     }
 }
 
+/// Which half of the game connection a segment belongs to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StreamDirection {
+    /// Server to client: notifications and call returns.
+    Downstream,
+    /// Client to server: calls.
+    Upstream,
+}
+
 fn ip_to_str(ip: &[u8; 4]) -> String {
     format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3])
 }
 
+pub const FRAME_HEADER_SIZE: usize = 6;
+const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+// Out-of-order segments are buffered until the missing one arrives. Past these limits the
+// missing segment is considered lost and the stream skips ahead.
+const MAX_PENDING_SEGMENTS: usize = 256;
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+// A missing segment that isn't retransmitted within this time is lost as well, so a small gap
+// on a quiet stream doesn't hold back the segments after it until the limits are reached.
+const GAP_TIMEOUT: Duration = Duration::from_secs(5);
+// Segments this far away from the expected sequence number belong to a different stream.
+const MAX_SEQ_DISTANCE: i64 = 32 * 1024 * 1024;
+
+/// Reassembles one direction of a TCP connection and splits it into game frames.
+///
+/// Segments are keyed by their offset in the stream (rather than the raw 32-bit sequence
+/// number) so ordering survives sequence number wraparound.
 pub struct TCPReassembler {
-    pub cache: BTreeMap<usize, Vec<u8>>,
-    pub next_seq: Option<usize>,
-    pub _data: Vec<u8>,
+    cache: BTreeMap<u64, Vec<u8>>,
+    pending_bytes: usize,
+    next_seq: Option<u32>,
+    next_offset: u64,
+    resyncing: bool,
+    data: BytesMut,
+    gaps: u64,
+    /// When the stream started waiting for a missing segment.
+    gap_since: Option<Instant>,
 }
 
 impl TCPReassembler {
     pub fn new() -> Self {
         Self {
             cache: BTreeMap::new(),
+            pending_bytes: 0,
             next_seq: None,
-            _data: Vec::new(),
+            next_offset: 0,
+            resyncing: false,
+            data: BytesMut::new(),
+            gaps: 0,
+            gap_since: None,
         }
     }
 
-    pub fn clear_reassembler(&mut self, seq_number: usize) {
+    pub fn clear_reassembler(&mut self, seq_number: u32) {
+        self.reset();
+        self.next_seq = Some(seq_number);
+    }
+
+    /// Forgets the stream entirely. The next segment pushed becomes the start of the stream.
+    pub fn reset(&mut self) {
         self.cache = BTreeMap::new();
-        self.next_seq = Some(seq_number)
+        self.pending_bytes = 0;
+        self.next_seq = None;
+        self.next_offset = 0;
+        self.resyncing = false;
+        self.data.clear();
+        self.gap_since = None;
+    }
+
+    /// Returns the number of skipped gaps and invalid frame headers since the last call.
+    pub fn take_gap_count(&mut self) -> u64 {
+        std::mem::take(&mut self.gaps)
     }
+
+    /// Adds a TCP segment. Duplicates and already consumed bytes are dropped, segments
+    /// from the future are buffered until the stream catches up to them.
+    pub fn push_segment(&mut self, seq_number: u32, payload: &[u8]) {
+        if payload.is_empty() {
+            return;
+        }
+        let next_seq = *self.next_seq.get_or_insert(seq_number);
+        let distance = i64::from(seq_number.wrapping_sub(next_seq) as i32);
+        if distance.abs() > MAX_SEQ_DISTANCE {
+            warn!("TCP segment {seq_number} is too far from expected {next_seq}, restarting stream");
+            self.clear_reassembler(seq_number);
+            return self.push_segment(seq_number, payload);
+        }
+
+        let mut payload = payload;
+        let mut offset = self.next_offset as i64 + distance;
+        if offset < self.next_offset as i64 {
+            // retransmission, possibly carrying some new bytes at the end
+            let consumed = (self.next_offset as i64 - offset) as usize;
+            if consumed >= payload.len() {
+                return;
+            }
+            payload = &payload[consumed..];
+            offset = self.next_offset as i64;
+        }
+        let offset = offset as u64;
+        match self.cache.get(&offset) {
+            Some(cached) if cached.len() >= payload.len() => return,
+            Some(cached) => self.pending_bytes -= cached.len(),
+            None => {}
+        }
+        self.pending_bytes += payload.len();
+        self.cache.insert(offset, payload.to_vec());
+
+        self.drain_cache();
+        if self.cache.is_empty() {
+            self.gap_since = None;
+            return;
+        }
+        let gap_since = *self.gap_since.get_or_insert_with(Instant::now);
+        if self.cache.len() > MAX_PENDING_SEGMENTS
+            || self.pending_bytes > MAX_PENDING_BYTES
+            || gap_since.elapsed() >= GAP_TIMEOUT
+        {
+            self.skip_gap();
+        }
+    }
+
+    /// Moves contiguous cached segments into the stream buffer, trimming overlaps.
+    fn drain_cache(&mut self) {
+        while let Some(entry) = self.cache.first_entry() {
+            let offset = *entry.key();
+            if offset > self.next_offset {
+                break;
+            }
+            let segment = entry.remove();
+            self.pending_bytes -= segment.len();
+            let overlap = (self.next_offset - offset) as usize;
+            if overlap < segment.len() {
+                self.data.extend_from_slice(&segment[overlap..]);
+                self.advance(segment.len() - overlap);
+            }
+        }
+    }
+
+    /// Gives up on the missing bytes and continues at the first buffered segment.
+    /// The partial frame in the stream buffer is lost, so framing resynchronizes.
+    fn skip_gap(&mut self) {
+        let Some(&first_offset) = self.cache.keys().next() else {
+            return;
+        };
+        let missing = first_offset - self.next_offset;
+        warn!(
+            "TCP stream gap of {missing} bytes at seq {:?}, skipping ahead ({} segments buffered)",
+            self.next_seq,
+            self.cache.len()
+        );
+        self.data.clear();
+        self.resyncing = true;
+        self.gaps += 1;
+        self.advance(missing as usize);
+        self.drain_cache();
+        // a further gap gets its own timeout
+        self.gap_since = (!self.cache.is_empty()).then(Instant::now);
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.next_offset += len as u64;
+        self.next_seq = self.next_seq.map(|seq| seq.wrapping_add(len as u32));
+    }
+
+    /// Splits the next complete length-prefixed frame off the stream buffer without copying.
+    /// On an implausible frame header the buffer is scanned for the next valid header.
+    pub fn next_frame(&mut self) -> Option<Bytes> {
+        if self.data.len() < FRAME_HEADER_SIZE {
+            return None;
+        }
+        if !is_frame_header(&self.data) {
+            if !self.resyncing {
+                warn!("Invalid frame header {:?}, resynchronizing", &self.data[..FRAME_HEADER_SIZE]);
+                self.resyncing = true;
+                self.gaps += 1;
+            }
+            let next_header = (1..=self.data.len() - FRAME_HEADER_SIZE)
+                .find(|&i| is_frame_header(&self.data[i..]));
+            let Some(start) = next_header else {
+                // the next header may start in the last few bytes
+                self.data.advance(self.data.len() - (FRAME_HEADER_SIZE - 1));
+                return None;
+            };
+            self.data.advance(start);
+        }
+        if self.resyncing {
+            info!("TCP stream resynchronized on a frame header");
+            self.resyncing = false;
+        }
+
+        let frame_size =
+            u32::from_be_bytes([self.data[0], self.data[1], self.data[2], self.data[3]]) as usize;
+        if self.data.len() < frame_size {
+            return None;
+        }
+        Some(self.data.split_to(frame_size).freeze())
+    }
+}
+
+impl Default for TCPReassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `data` starts with a plausible frame header: a sane length and a known fragment type.
+fn is_frame_header(data: &[u8]) -> bool {
+    if data.len() < FRAME_HEADER_SIZE {
+        return false;
+    }
+    let frame_size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let fragment_type = u16::from_be_bytes([data[4], data[5]]) & 0x7fff;
+    (FRAME_HEADER_SIZE..=MAX_FRAME_SIZE).contains(&frame_size)
+        && !matches!(FragmentType::from(fragment_type), FragmentType::None)
 }
 
 // Binary reader implementation
+/// Reads big-endian values off the front of a `Bytes` buffer.
+/// Byte slices are handed out as `Bytes` views of the same allocation, without copying.
 pub struct BinaryReader {
-    pub cursor: Cursor<Vec<u8>>,
+    data: Bytes,
 }
 
 impl BinaryReader {
-    pub fn from(data: Vec<u8>) -> Self {
-        Self {
-            cursor: Cursor::new(data),
+    pub fn from(data: impl Into<Bytes>) -> Self {
+        Self { data: data.into() }
+    }
+
+    fn ensure_remaining(&self, count: usize) -> io::Result<()> {
+        if self.data.remaining() < count {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("needed {count} bytes, {} remaining", self.data.remaining()),
+            ));
         }
+        Ok(())
     }
 
     pub fn read_u16(&mut self) -> io::Result<u16> {
-        self.cursor.read_u16::<BigEndian>()
+        self.ensure_remaining(2)?;
+        Ok(self.data.get_u16())
     }
 
     pub fn read_u32(&mut self) -> io::Result<u32> {
-        self.cursor.read_u32::<BigEndian>()
+        self.ensure_remaining(4)?;
+        Ok(self.data.get_u32())
     }
 
     pub fn peek_u32(&mut self) -> io::Result<u32> {
-        let pos = self.cursor.position();
-        let value = self.cursor.read_u32::<BigEndian>()?;
-        self.cursor.set_position(pos);
-        Ok(value)
+        self.ensure_remaining(4)?;
+        Ok(u32::from_be_bytes([self.data[0], self.data[1], self.data[2], self.data[3]]))
     }
 
     pub fn read_u64(&mut self) -> io::Result<u64> {
-        self.cursor.read_u64::<BigEndian>()
+        self.ensure_remaining(8)?;
+        Ok(self.data.get_u64())
     }
 
     pub fn read_string(&mut self) -> io::Result<String> {
-        let mut s = String::new();
-        self.cursor.read_to_string(&mut s)?;
-        Ok(s)
+        let bytes = self.read_remaining();
+        String::from_utf8(bytes.to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 
-    pub fn read_bytes(&mut self, count: usize) -> io::Result<Vec<u8>> {
-        let mut buffer = vec![0u8; count];
-        self.cursor.read_exact(&mut buffer)?;
-        Ok(buffer)
+    pub fn read_bytes(&mut self, count: usize) -> io::Result<Bytes> {
+        self.ensure_remaining(count)?;
+        Ok(self.data.split_to(count))
     }
 
-    pub fn read_remaining(&mut self) -> &[u8] {
-        let pos = self.cursor.position() as usize;
-        let buf = self.cursor.get_ref();
-        &buf[pos..]
+    pub fn read_remaining(&mut self) -> Bytes {
+        std::mem::take(&mut self.data)
     }
 
     pub fn remaining(&self) -> usize {
-        let total_len = self.cursor.get_ref().len() as u64;
-        let current_pos = self.cursor.position();
-        (total_len.saturating_sub(current_pos)) as usize
+        self.data.remaining()
+    }
+}
+
+/// Keeps at most `max_files - 1` files named `{prefix}*.{extension}` in `directory`, so the
+/// next one fits under the cap. File names must embed their start time.
+pub fn remove_old_files(directory: &Path, prefix: &str, extension: &str, max_files: usize) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == extension)
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(prefix))
+        })
+        .collect();
+    // file names embed the start time, so sorting by name sorts by age
+    files.sort();
+    let excess = files.len().saturating_sub(max_files - 1);
+    for path in files.into_iter().take(excess) {
+        if let Err(e) = fs::remove_file(&path) {
+            warn!("Failed to remove old file {}: {e}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packets::utils::{TCPReassembler, GAP_TIMEOUT};
+    use std::time::Instant;
+
+    fn frame(fragment_type: u16, body: &[u8]) -> Vec<u8> {
+        let mut frame = ((body.len() + 6) as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&fragment_type.to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    fn frames(reassembler: &mut TCPReassembler) -> Vec<Vec<u8>> {
+        std::iter::from_fn(|| reassembler.next_frame())
+            .map(|frame| frame.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_reassembler_reorders_segments() {
+        let stream = [frame(2, b"first"), frame(2, b"second")].concat();
+        let mut reassembler = TCPReassembler::new();
+        reassembler.clear_reassembler(1000);
+        reassembler.push_segment(1007, &stream[7..15]);
+        reassembler.push_segment(1015, &stream[15..]);
+        assert!(frames(&mut reassembler).is_empty());
+        reassembler.push_segment(1000, &stream[..7]);
+        assert_eq!(frames(&mut reassembler), vec![frame(2, b"first"), frame(2, b"second")]);
     }
 
-    pub const fn len(&self) -> usize {
-        self.cursor.get_ref().len()
+    #[test]
+    fn test_reassembler_drops_duplicates_and_trims_overlaps() {
+        let stream = frame(2, b"retransmitted payload");
+        let mut reassembler = TCPReassembler::new();
+        reassembler.clear_reassembler(0);
+        reassembler.push_segment(0, &stream[..10]);
+        reassembler.push_segment(0, &stream[..10]);
+        reassembler.push_segment(5, &stream[5..20]);
+        reassembler.push_segment(15, &stream[15..]);
+        reassembler.push_segment(12, &stream[12..18]);
+        assert_eq!(frames(&mut reassembler), vec![stream]);
     }
 
-    // pub fn splice_remaining(&mut self, data: &[u8]) {
-    //     let start_range = self.cursor.position() as usize;
-    //     let buf = self.cursor.get_mut();
-    //     buf.splice(start_range.., data.iter().cloned());
-    // }
+    #[test]
+    fn test_reassembler_handles_sequence_wraparound() {
+        let stream = [frame(2, b"before wrap"), frame(6, b"after wrap")].concat();
+        let start = u32::MAX - 8;
+        let mut reassembler = TCPReassembler::new();
+        reassembler.clear_reassembler(start);
+        reassembler.push_segment(start.wrapping_add(12), &stream[12..]);
+        reassembler.push_segment(start, &stream[..12]);
+        assert_eq!(frames(&mut reassembler), vec![frame(2, b"before wrap"), frame(6, b"after wrap")]);
+    }
+
+    #[test]
+    fn test_reassembler_resyncs_after_lost_segment() {
+        let lost = frame(2, &[0xab; 64]);
+        let after = frame(2, b"after the gap");
+        let mut reassembler = TCPReassembler::new();
+        reassembler.clear_reassembler(0);
+        reassembler.push_segment(0, &lost[..10]);
+        // the rest of `lost` never arrives, the next segment starts mid-frame
+        let mut seq = lost.len() as u32 + 20;
+        reassembler.push_segment(seq, &[0xcd; 20]);
+        seq += 20;
+        for _ in 0..300 {
+            reassembler.push_segment(seq, &after);
+            seq += after.len() as u32;
+        }
+        let frames = frames(&mut reassembler);
+        assert!(!frames.is_empty());
+        assert!(frames.iter().all(|frame| *frame == after));
+    }
+
+    #[test]
+    fn test_reassembler_skips_gap_after_timeout() {
+        let lost = frame(2, b"lost");
+        let after = [frame(2, b"after"), frame(2, b"the gap")].concat();
+        let mut reassembler = TCPReassembler::new();
+        reassembler.clear_reassembler(0);
+        let seq = lost.len() as u32;
+        reassembler.push_segment(seq, &after[..11]);
+        assert!(frames(&mut reassembler).is_empty());
+        assert_eq!(reassembler.take_gap_count(), 0);
+
+        // the missing segment isn't retransmitted in time
+        reassembler.gap_since = Some(Instant::now() - GAP_TIMEOUT);
+        reassembler.push_segment(seq + 11, &after[11..]);
+        assert_eq!(frames(&mut reassembler), vec![frame(2, b"after"), frame(2, b"the gap")]);
+        assert_eq!(reassembler.take_gap_count(), 1);
+        assert_eq!(reassembler.gap_since, None);
+        // it's then dropped as already skipped
+        reassembler.push_segment(0, &lost);
+        assert!(frames(&mut reassembler).is_empty());
+    }
 }
@@ -0,0 +1,40 @@
+use crate::packets::capture_source::{CaptureSource, NextPacket};
+use log::{error, info};
+use windivert::WinDivert;
+use windivert::layer::NetworkLayer;
+use windivert::prelude::WinDivertFlags;
+
+pub struct WinDivertSource {
+    handle: WinDivert<NetworkLayer>,
+    buffer: Vec<u8>,
+}
+
+impl WinDivertSource {
+    pub fn open() -> Result<Self, String> {
+        let handle = WinDivert::network("!loopback && ip && tcp", 0, WinDivertFlags::new().set_sniff())
+            .map_err(|e| format!("Failed to initialize WinDivert: {e}"))?;
+        info!("WinDivert handle opened!");
+        Ok(Self {
+            handle,
+            buffer: vec![0u8; 10 * 1024 * 1024],
+        })
+    }
+}
+
+impl CaptureSource for WinDivertSource {
+    fn name(&self) -> &'static str {
+        "WinDivert"
+    }
+
+    // WinDivert has no read timeout, a restarted reader thread exits with the next packet
+    fn next_packet(&mut self) -> NextPacket<'_> {
+        let len = match self.handle.recv(Some(&mut self.buffer)) {
+            Ok(packet) => packet.data.len(),
+            Err(e) => {
+                error!("WinDivert recv failed: {e}");
+                return NextPacket::Closed;
+            }
+        };
+        NextPacket::Packet(&self.buffer[..len])
+    }
+}
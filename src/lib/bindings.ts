@@ -8,6 +8,12 @@ export const commands = {
 async getCrowdsourcedMonster() : Promise<CrowdsourcedMonster | null> {
     return await TAURI_INVOKE("get_crowdsourced_monster");
 },
+/**
+ * Crowdsourced monsters seen on this server, the ones in view first.
+ */
+async getTrackedBosses() : Promise<TrackedBossStatus[]> {
+    return await TAURI_INVOKE("get_tracked_bosses");
+},
 async getCrowdsourcedMonsterOptions() : Promise<CrowdsourcedMonsterOption[]> {
     return await TAURI_INVOKE("get_crowdsourced_monster_options");
 },
@@ -34,6 +40,56 @@ async markCurrentCrowdsourcedLineDead() : Promise<Result<null, string>> {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+async getSessionRecording() : Promise<boolean> {
+    return await TAURI_INVOKE("get_session_recording");
+},
+async setSessionRecording(enabled: boolean) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_session_recording", { enabled }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getMessageJournalSettings() : Promise<MessageJournalSettings> {
+    return await TAURI_INVOKE("get_message_journal_settings");
+},
+async setMessageJournalSettings(settings: MessageJournalSettings) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_message_journal_settings", { settings }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getCaptureStatus() : Promise<CaptureStatus> {
+    return await TAURI_INVOKE("get_capture_status");
+},
+async restartCapture() : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("restart_capture") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getOpcodeDiscovery() : Promise<OpcodeDiscoveryReport> {
+    return await TAURI_INVOKE("get_opcode_discovery");
+},
+async setOpcodeDiscovery(enabled: boolean) : Promise<void> {
+    await TAURI_INVOKE("set_opcode_discovery", { enabled });
+},
+/**
+ * Returns the path of the exported JSON file.
+ */
+async exportOpcodeDiscovery() : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_opcode_discovery") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
@@ -47,8 +103,89 @@ async markCurrentCrowdsourcedLineDead() : Promise<Result<null, string>> {
 
 /** user-defined types **/
 
-export type CrowdsourcedMonster = { name: string; id: number; remote_id: string | null }
+export type CaptureStatus = { 
+/**
+ * Name of the open capture backend, `None` if it failed to open.
+ */
+source: string | null; source_error: string | null; 
+/**
+ * Detected game server connection.
+ */
+server: string | null; packets_seen: number; bytes_seen: number; reassembly_gaps: number; frames_decoded: number; 
+/**
+ * Reassembled frames that failed to decode, counted instead of `frames_decoded`.
+ */
+frame_failures: number; zstd_failures: number; 
+/**
+ * Protobuf decode failures keyed by message name.
+ */
+decode_failures: Partial<{ [key in string]: number }>; ms_since_last_packet: number | null; ms_since_last_frame: number | null; 
+/**
+ * Time without decoded frames after which the capture restarts itself, `None` when the
+ * watchdog is off.
+ */
+stall_timeout_secs: number | null }
+export type CrowdsourcedMonster = { name: string; id: number; remote_id: string | null; 
+/**
+ * Whether a monster with this id is currently in view of the local player.
+ */
+in_view: boolean }
 export type CrowdsourcedMonsterOption = { name: string; id: number; remote_id: string }
+export type HpSample = { timestamp_ms: number; hp: number }
+/**
+ * Why a tracked boss is no longer in view.
+ */
+export type LeftViewReason = "Dead" | 
+/**
+ * Walked out of range, or despawned without a reason.
+ */
+"Despawned" | "Destroyed" | 
+/**
+ * The local player changed scene or line.
+ */
+"Transferred" | 
+/**
+ * No update within `ENTITY_TTL_MS`.
+ */
+"Expired"
+export type MessageJournalSettings = { enabled: boolean; 
+/**
+ * Opcode names to journal, e.g. `SyncNearEntities`. Empty journals every opcode.
+ */
+include: string[]; 
+/**
+ * Opcode names to leave out, applied after `include`.
+ */
+exclude: string[] }
+export type OpcodeDiscoveryReport = { enabled: boolean; methods: UnknownMethod[] }
+export type PayloadSample = { size: number; 
+/**
+ * Hex of the payload, cut off after `MAX_SAMPLE_BYTES` bytes.
+ */
+hex: string; 
+/**
+ * Schemaless protobuf dump of the same bytes.
+ */
+wire_dump: string }
+export type SizeBucket = { 
+/**
+ * Inclusive upper bound of the bucket, `None` for the last one.
+ */
+up_to: number | null; count: number }
+export type TrackedBossStatus = { uid: number; monster_id: number; name: string; remote_id: string | null; line_id: number | null; curr_hp: number | null; max_hp: number | null; hp_pct: number | null; last_reported_hp_pct: number | null; in_view: boolean; 
+/**
+ * Why the boss left view, `None` while it is in view.
+ */
+left_view: LeftViewReason | null; 
+/**
+ * Whether its 0% report was sent.
+ */
+death_reported: boolean; 
+/**
+ * Most recent HP updates, oldest first.
+ */
+hp_history: HpSample[] }
+export type UnknownMethod = { method_id: number; count: number; min_size: number; max_size: number; mean_size: number; sizes: SizeBucket[]; samples: PayloadSample[] }
 
 /** tauri-specta globals **/
 